@@ -0,0 +1,281 @@
+use crate::{deserialize_slice, CancellationToken, Error, Result, Runtime, Subscriber};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// The future returned by [`AsyncSubscriber::handle_command`].
+pub type CommandFuture<'a> = Pin<Box<dyn Future<Output = Result<serde_json::Value>> + Send + 'a>>;
+
+/// Like [`Subscriber`], but commands are handled by futures, so that handlers can await network
+/// IO without blocking other work. Wrap an implementation in an [`AsyncModule`] to hand it to the
+/// `Runtime`.
+pub trait AsyncSubscriber: Send + Sync {
+    /// Handler for the command `name` on `implementation_id` with the given `parameters`. The
+    /// output of the returned future will be returned as the result of the call.
+    ///
+    /// `cancellation` is cancelled once the caller gave up waiting for the result, see
+    /// [`AsyncModule::command_timeout`], and when the `Runtime` shuts down. Cancellation is
+    /// cooperative: The future is still awaited, so a long running handler must check the token
+    /// or select its work against [`CancellationToken::cancelled`] and return early.
+    fn handle_command<'a>(
+        &'a self,
+        implementation_id: &'a str,
+        name: &'a str,
+        parameters: HashMap<String, serde_json::Value>,
+        cancellation: CancellationToken,
+    ) -> CommandFuture<'a>;
+
+    /// See [`Subscriber::handle_variable`]. Variable updates are not awaited by anybody, so
+    /// this stays synchronous, spawn work on [`AsyncModule::tokio`] if needed.
+    fn handle_variable(
+        &self,
+        _implementation_id: &str,
+        _index: usize,
+        _name: &str,
+        _value: serde_json::Value,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// See [`Subscriber::handle_variable_raw`].
+    fn handle_variable_raw(
+        &self,
+        implementation_id: &str,
+        index: usize,
+        name: &str,
+        data: &[u8],
+    ) -> Result<()> {
+        self.handle_variable(implementation_id, index, name, deserialize_slice(data)?)
+    }
+
+    fn subscribes_to(&self, _implementation_id: &str, _name: &str) -> bool {
+        true
+    }
+
+    fn on_error(&self, _err: Error) {}
+
+    /// See [`Subscriber::handle_variable_undecoded`].
+    fn handle_variable_undecoded(
+        &self,
+        implementation_id: &str,
+        index: usize,
+        name: &str,
+        _raw: &[u8],
+        err: Error,
+    ) {
+        log::warn!(
+            "Dropping variable '{name}' of '{implementation_id}' from connection {index}: {err}"
+        );
+        self.on_error(err);
+    }
+
+    fn on_ready(&self, _runtime: &Runtime) {}
+
+    fn on_requirement_ready(&self, _requirement_id: &str, _index: usize) {}
+
+    fn on_disconnected(&self) {}
+
+    fn on_reconnected(&self) {}
+}
+
+/// Adapts an [`AsyncSubscriber`] to the synchronous [`Subscriber`] that the `Runtime`
+/// dispatches to, by driving the command futures on an internal tokio runtime.
+///
+/// The cxx bridge calls into Rust synchronously, so the C++ thread delivering the command waits
+/// until the future completes: `block_on` polls the future on that thread, while everything the
+/// future spawns runs on the tokio worker threads. This has two consequences for handlers:
+/// - Other commands arriving on the same C++ thread are only dispatched after the current future
+///   resolved, so long running work should be spawned rather than awaited where possible.
+/// - Handlers must not call `block_on` on the tokio runtime themselves (tokio panics on nested
+///   `block_on`), and should not make synchronous `Runtime::call_command` calls that wait for a
+///   command to be handled by this very module, as that would deadlock.
+pub struct AsyncModule<T> {
+    module_impl: T,
+    tokio: tokio::runtime::Runtime,
+    /// See `command_timeout`.
+    command_timeout: Duration,
+    /// The token of the `Runtime`, cancelled when it shuts down, set in `on_ready`. The parent of
+    /// the tokens of all calls.
+    shutdown: OnceLock<CancellationToken>,
+}
+
+impl<T: AsyncSubscriber> AsyncModule<T> {
+    /// How long callers of our commands wait for the result before they give up, unless they set
+    /// a timeout of their own.
+    pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(300);
+
+    pub fn new(module_impl: T) -> Result<Self> {
+        let tokio = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| Error::ModuleCreation(format!("could not start tokio: {e}")))?;
+        Ok(Self {
+            module_impl,
+            tokio,
+            command_timeout: Self::DEFAULT_COMMAND_TIMEOUT,
+            shutdown: OnceLock::new(),
+        })
+    }
+
+    /// Cancels the token of a command call once it ran for `timeout`, as its caller gave up
+    /// waiting for the result by then, [`AsyncModule::DEFAULT_COMMAND_TIMEOUT`] by default. The
+    /// callee cannot know the timeout a caller passed to `Runtime::call_command_timeout`, so
+    /// modules whose callers wait less should set it accordingly.
+    pub fn command_timeout(mut self, timeout: Duration) -> Self {
+        self.command_timeout = timeout;
+        self
+    }
+
+    /// The tokio runtime the command handlers are driven on, i.e. for spawning background work.
+    pub fn tokio(&self) -> &tokio::runtime::Runtime {
+        &self.tokio
+    }
+}
+
+impl<T: AsyncSubscriber> Subscriber for AsyncModule<T> {
+    fn handle_command(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        parameters: HashMap<String, serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        let parent = self.shutdown.get().cloned().unwrap_or_default();
+        let call = CallCancellation::new(&parent, self.command_timeout);
+        self.tokio.block_on(self.module_impl.handle_command(
+            implementation_id,
+            name,
+            parameters,
+            call.token.clone(),
+        ))
+    }
+
+    fn handle_variable(
+        &self,
+        implementation_id: &str,
+        index: usize,
+        name: &str,
+        value: serde_json::Value,
+    ) -> Result<()> {
+        self.module_impl
+            .handle_variable(implementation_id, index, name, value)
+    }
+
+    fn handle_variable_raw(
+        &self,
+        implementation_id: &str,
+        index: usize,
+        name: &str,
+        data: &[u8],
+    ) -> Result<()> {
+        self.module_impl
+            .handle_variable_raw(implementation_id, index, name, data)
+    }
+
+    fn subscribes_to(&self, implementation_id: &str, name: &str) -> bool {
+        self.module_impl.subscribes_to(implementation_id, name)
+    }
+
+    fn on_error(&self, err: Error) {
+        self.module_impl.on_error(err);
+    }
+
+    fn handle_variable_undecoded(
+        &self,
+        implementation_id: &str,
+        index: usize,
+        name: &str,
+        raw: &[u8],
+        err: Error,
+    ) {
+        self.module_impl
+            .handle_variable_undecoded(implementation_id, index, name, raw, err);
+    }
+
+    fn on_ready(&self, runtime: &Runtime) {
+        // This is where we first get the `Runtime`, so calls before it was ready are only cancelled
+        // by their timeout.
+        let _ = self.shutdown.set(runtime.cancellation_token());
+        self.module_impl.on_ready(runtime);
+    }
+
+    fn on_requirement_ready(&self, requirement_id: &str, index: usize) {
+        self.module_impl.on_requirement_ready(requirement_id, index);
+    }
+
+    fn on_disconnected(&self) {
+        self.module_impl.on_disconnected();
+    }
+
+    fn on_reconnected(&self) {
+        self.module_impl.on_reconnected();
+    }
+}
+
+/// The token of one command call, a child of `parent` that is also cancelled once the call ran
+/// for `timeout`. The timeout is watched by a thread of its own, which ends when this is dropped.
+struct CallCancellation {
+    token: CancellationToken,
+    /// Dropped with the call, which wakes up the thread.
+    _done: mpsc::Sender<()>,
+}
+
+impl CallCancellation {
+    fn new(parent: &CancellationToken, timeout: Duration) -> Self {
+        let token = parent.child_token();
+        let (done, call_done) = mpsc::channel();
+        let timed_out = token.clone();
+        std::thread::spawn(move || {
+            if call_done.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                timed_out.cancel();
+            }
+        });
+        Self { token, _done: done }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// Waits up to a minute for `token` to be cancelled, returns whether it was.
+    fn wait_for(token: &CancellationToken) -> bool {
+        let start = Instant::now();
+        while !token.is_cancelled() && start.elapsed() < Duration::from_secs(60) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        token.is_cancelled()
+    }
+
+    #[test]
+    fn call_is_cancelled_when_it_times_out() {
+        let shutdown = CancellationToken::new();
+        let call = CallCancellation::new(&shutdown, Duration::from_millis(10));
+        assert!(wait_for(&call.token));
+        assert!(!shutdown.is_cancelled());
+    }
+
+    #[test]
+    fn finished_call_is_not_cancelled() {
+        let call = CallCancellation::new(&CancellationToken::new(), Duration::from_millis(10));
+        let token = call.token.clone();
+        drop(call);
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!token.is_cancelled());
+    }
+
+    #[test]
+    fn calls_are_cancelled_at_shutdown() {
+        let shutdown = CancellationToken::new();
+        let calls: Vec<_> = (0..2)
+            .map(|_| CallCancellation::new(&shutdown, Duration::from_secs(60)))
+            .collect();
+        calls[0].token.cancel();
+        assert!(!calls[1].token.is_cancelled());
+        shutdown.cancel();
+        assert!(calls.iter().all(|call| call.token.is_cancelled()));
+    }
+}
@@ -0,0 +1,144 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+
+/// Tells long running command handlers, i.e. a firmware update, that they should stop, see
+/// `Runtime::cancellation_token`. Cancellation is cooperative: Nothing is interrupted, handlers
+/// must check [`CancellationToken::is_cancelled`] regularly or await
+/// [`CancellationToken::cancelled`] next to their work.
+///
+/// Clones share their state, so cancelling one cancels all of them. A token from
+/// [`CancellationToken::child_token`] is cancelled with its parent, but can also be cancelled on
+/// its own, i.e. for a single command call.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    /// The tasks waiting in `cancelled`.
+    wakers: Mutex<Vec<Waker>>,
+    /// The tokens of `child_token` that are still alive.
+    children: Mutex<Vec<Weak<Inner>>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the token and its children and wakes up everybody waiting for them. Cancelling
+    /// twice is a no-op.
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    /// Returns a new token that is cancelled when this one is, right away if this one already is.
+    /// Cancelling the child does not cancel this token.
+    pub fn child_token(&self) -> CancellationToken {
+        let child = CancellationToken::new();
+        let mut children = self.inner.children.lock().unwrap();
+        // `cancel` sets the flag before it takes the children, so we either see the flag or it
+        // sees the child.
+        if self.is_cancelled() {
+            child.cancel();
+        } else {
+            children.retain(|child| child.strong_count() > 0);
+            children.push(Arc::downgrade(&child.inner));
+        }
+        child
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns a future that resolves once the token is cancelled, for `select!`ing it against the
+    /// work of an async handler.
+    pub fn cancelled(&self) -> Cancelled<'_> {
+        Cancelled { token: self }
+    }
+}
+
+impl Inner {
+    fn cancel(&self) {
+        let wakers = {
+            let mut wakers = self.wakers.lock().unwrap();
+            self.cancelled.store(true, Ordering::SeqCst);
+            std::mem::take(&mut *wakers)
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+        let children = std::mem::take(&mut *self.children.lock().unwrap());
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+/// The future returned by [`CancellationToken::cancelled`].
+#[derive(Debug)]
+pub struct Cancelled<'a> {
+    token: &'a CancellationToken,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+        let mut wakers = self.token.inner.wakers.lock().unwrap();
+        // `cancel` sets the flag under the lock, so checking again makes sure we do not miss it.
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn children_are_cancelled_with_their_parent() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let grandchild = child.child_token();
+        assert!(!grandchild.is_cancelled());
+        parent.cancel();
+        assert!(child.is_cancelled());
+        assert!(grandchild.is_cancelled());
+        assert!(parent.child_token().is_cancelled());
+    }
+
+    #[test]
+    fn cancelling_a_child_leaves_the_parent_and_siblings_alone() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let sibling = parent.child_token();
+        child.cancel();
+        assert!(!parent.is_cancelled());
+        assert!(!sibling.is_cancelled());
+    }
+
+    #[test]
+    fn dropped_children_are_forgotten() {
+        let parent = CancellationToken::new();
+        for _ in 0..100 {
+            parent.child_token();
+        }
+        let _child = parent.child_token();
+        assert_eq!(parent.inner.children.lock().unwrap().len(), 1);
+    }
+}
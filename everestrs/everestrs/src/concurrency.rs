@@ -0,0 +1,63 @@
+use crate::{Error, Result};
+use std::sync::{Condvar, Mutex};
+
+/// What happens to a command that arrives while `RuntimeBuilder::max_concurrent_commands`
+/// commands are already running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverloadPolicy {
+    /// Wait until one of the running commands finished.
+    #[default]
+    Queue,
+    /// Fail the call right away with `Error::Overloaded`.
+    Reject,
+}
+
+/// Bounds the number of command handlers running at the same time.
+pub(crate) struct CommandLimit {
+    max: usize,
+    policy: OverloadPolicy,
+    running: Mutex<usize>,
+    finished: Condvar,
+}
+
+/// One of the running commands, gives its slot back when dropped.
+pub(crate) struct CommandPermit<'a> {
+    limit: &'a CommandLimit,
+}
+
+impl CommandLimit {
+    pub(crate) fn new(max: usize, policy: OverloadPolicy) -> Self {
+        Self {
+            max,
+            policy,
+            running: Mutex::new(0),
+            finished: Condvar::new(),
+        }
+    }
+
+    /// Takes a slot for a command, waiting for one or failing with `Error::Overloaded` if all are
+    /// taken, depending on the policy.
+    pub(crate) fn acquire(&self) -> Result<CommandPermit<'_>> {
+        let mut running = self.running.lock().unwrap();
+        if *running >= self.max {
+            match self.policy {
+                OverloadPolicy::Queue => {
+                    running = self
+                        .finished
+                        .wait_while(running, |running| *running >= self.max)
+                        .unwrap();
+                }
+                OverloadPolicy::Reject => return Err(Error::Overloaded),
+            }
+        }
+        *running += 1;
+        Ok(CommandPermit { limit: self })
+    }
+}
+
+impl Drop for CommandPermit<'_> {
+    fn drop(&mut self) {
+        *self.limit.running.lock().unwrap() -= 1;
+        self.limit.finished.notify_one();
+    }
+}
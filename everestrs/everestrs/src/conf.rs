@@ -0,0 +1,70 @@
+use crate::{Error, Result};
+use std::path::{Path, PathBuf};
+
+/// A config yaml merged from a base and overlays by `RuntimeBuilder::conf_overlay`, written to a
+/// temporary file for libframework, which only takes a path. The file is removed on drop.
+pub(crate) struct MergedConf {
+    pub(crate) path: PathBuf,
+}
+
+impl MergedConf {
+    /// Merges the `overlays` onto `base`, in order, and writes the result. libframework merges
+    /// the `user-config/<name>` next to its config itself, which it would not find next to the
+    /// temporary file, so it is merged right after `base` here.
+    pub(crate) fn write(module_id: &str, base: &Path, overlays: &[PathBuf]) -> Result<Self> {
+        let mut conf = read(base)?;
+        if let (Some(dir), Some(name)) = (base.parent(), base.file_name()) {
+            let user_config = dir.join("user-config").join(name);
+            if user_config.exists() {
+                merge_patch(&mut conf, read(&user_config)?);
+            }
+        }
+        for overlay in overlays {
+            merge_patch(&mut conf, read(overlay)?);
+        }
+
+        // JSON is valid YAML, and libframework insists on the extension.
+        let path =
+            std::env::temp_dir().join(format!("everestrs-{module_id}-{}.yaml", std::process::id()));
+        let data = serde_json::to_vec(&conf).expect("Serialization of data cannot fail.");
+        std::fs::write(&path, data).map_err(|e| {
+            Error::ModuleCreation(format!("could not write '{}': {e}", path.display()))
+        })?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for MergedConf {
+    fn drop(&mut self) {
+        // Nothing is lost if it stays behind in the temporary directory.
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn read(path: &Path) -> Result<serde_json::Value> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| Error::ModuleCreation(format!("could not read '{}': {e}", path.display())))?;
+    serde_yaml::from_str(&content)
+        .map_err(|e| Error::ModuleCreation(format!("could not parse '{}': {e}", path.display())))
+}
+
+/// Applies `patch` to `target` like a JSON merge patch (RFC 7386), which is what libframework
+/// does for the user config: Mappings are merged key by key, `null` removes a key and everything
+/// else replaces the value of `target`, so lists are not concatenated.
+fn merge_patch(target: &mut serde_json::Value, patch: serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    let target = target.as_object_mut().expect("Just made it an object.");
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(&key);
+        } else {
+            merge_patch(target.entry(key).or_insert(serde_json::Value::Null), value);
+        }
+    }
+}
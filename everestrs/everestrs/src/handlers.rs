@@ -0,0 +1,193 @@
+use crate::{Error, Result, Subscriber};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Handles a command registered with `Runtime::on_command`, gets the arguments of the call.
+pub type CommandClosure =
+    Box<dyn Fn(HashMap<String, serde_json::Value>) -> Result<serde_json::Value> + Send + Sync>;
+
+/// Handles a variable registered with `Runtime::on_variable`, gets the connection index and the
+/// value.
+pub type VariableClosure = Box<dyn Fn(usize, serde_json::Value) -> Result<()> + Send + Sync>;
+
+/// The `Subscriber` that `Runtime::on_command` and `Runtime::on_variable` install, which routes to
+/// the registered closures by implementation or requirement id and name.
+#[derive(Default)]
+pub(crate) struct Handlers {
+    commands: RwLock<HashMap<(String, String), CommandClosure>>,
+    variables: RwLock<HashMap<(String, String), VariableClosure>>,
+}
+
+impl Handlers {
+    pub(crate) fn add_command(&self, implementation_id: &str, name: &str, handler: CommandClosure) {
+        self.commands
+            .write()
+            .unwrap()
+            .insert((implementation_id.to_string(), name.to_string()), handler);
+    }
+
+    pub(crate) fn add_variable(&self, requirement_id: &str, name: &str, handler: VariableClosure) {
+        self.variables
+            .write()
+            .unwrap()
+            .insert((requirement_id.to_string(), name.to_string()), handler);
+    }
+}
+
+impl Subscriber for Handlers {
+    fn handle_command(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        parameters: HashMap<String, serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        let commands = self.commands.read().unwrap();
+        let handler = commands
+            .get(&(implementation_id.to_string(), name.to_string()))
+            .ok_or_else(|| Error::UnhandledCommand {
+                implementation_id: implementation_id.to_string(),
+                name: name.to_string(),
+            })?;
+        handler(parameters)
+    }
+
+    fn handle_variable(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+        value: serde_json::Value,
+    ) -> Result<()> {
+        match self
+            .variables
+            .read()
+            .unwrap()
+            .get(&(requirement_id.to_string(), name.to_string()))
+        {
+            Some(handler) => handler(index, value),
+            None => Ok(()),
+        }
+    }
+
+    fn subscribes_to(&self, requirement_id: &str, name: &str) -> bool {
+        self.variables
+            .read()
+            .unwrap()
+            .contains_key(&(requirement_id.to_string(), name.to_string()))
+    }
+
+    fn on_error(&self, err: Error) {
+        log::error!("{err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{Interface, Manifest};
+    use crate::testing::RecordingRegistrar;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn registering_and_handling_from_many_threads() {
+        const THREADS: usize = 8;
+        const COMMANDS: usize = 50;
+        let handlers = Handlers::default();
+        std::thread::scope(|scope| {
+            for thread in 0..THREADS {
+                let handlers = &handlers;
+                scope.spawn(move || {
+                    let implementation_id = thread.to_string();
+                    for command in 0..COMMANDS {
+                        let name = command.to_string();
+                        handlers.add_command(
+                            &implementation_id,
+                            &name,
+                            Box::new(move |_| Ok((thread * COMMANDS + command).into())),
+                        );
+                        // Also calls the commands other threads are registering meanwhile.
+                        for id in [thread, (thread + 1) % THREADS] {
+                            match handlers.handle_command(&id.to_string(), &name, HashMap::new()) {
+                                Ok(result) => assert_eq!(result, id * COMMANDS + command),
+                                Err(Error::UnhandledCommand { .. }) => assert_ne!(id, thread),
+                                Err(err) => panic!("{err}"),
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(handlers.commands.read().unwrap().len(), THREADS * COMMANDS);
+    }
+
+    #[test]
+    fn same_variable_name_on_different_requirements() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let handlers = Arc::new(Handlers::default());
+        for requirement_id in ["evse_a", "evse_b"] {
+            let received = received.clone();
+            handlers.add_variable(
+                requirement_id,
+                "status",
+                Box::new(move |index, value| {
+                    received
+                        .lock()
+                        .unwrap()
+                        .push((requirement_id, index, value));
+                    Ok(())
+                }),
+            );
+        }
+
+        handlers
+            .handle_variable("evse_b", 1, "status", "Charging".into())
+            .unwrap();
+        handlers
+            .handle_variable("evse_a", 0, "status", "Idle".into())
+            .unwrap();
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                ("evse_b", 1, serde_json::json!("Charging")),
+                ("evse_a", 0, serde_json::json!("Idle")),
+            ]
+        );
+
+        // Both requirements have their own subscription, the one on `meter` is not wanted.
+        let manifest: Manifest = serde_json::from_value(serde_json::json!({
+            "description": "A module",
+            "provides": {},
+            "requires": {
+                "evse_a": {"interface": "evse"},
+                "evse_b": {"interface": "evse"},
+                "meter": {"interface": "evse"}
+            },
+            "metadata": {"license": "Apache-2.0", "authors": ["everest"]}
+        }))
+        .unwrap();
+        let evse: Interface = serde_json::from_value(serde_json::json!({
+            "description": "An EVSE",
+            "vars": {"status": {"type": "string", "description": "The status"}}
+        }))
+        .unwrap();
+        let mut registrar = RecordingRegistrar::new()
+            .with_connections("evse_a", 1)
+            .with_connections("evse_b", 1)
+            .with_connections("meter", 1)
+            .with_subscriber(handlers);
+        crate::wire_manifest(
+            &manifest,
+            &BTreeMap::from([("evse".to_string(), evse)]),
+            &mut registrar,
+        )
+        .unwrap();
+        assert_eq!(
+            registrar.subscribed_variables,
+            vec![
+                ("evse_a".to_string(), 0, "status".to_string()),
+                ("evse_b".to_string(), 0, "status".to_string()),
+            ]
+        );
+    }
+}
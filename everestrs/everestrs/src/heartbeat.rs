@@ -0,0 +1,49 @@
+use crate::Runtime;
+use std::sync::{mpsc, Weak};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// A thread publishing an incrementing counter on a variable, see `Runtime::start_heartbeat`.
+pub(crate) struct Heartbeat {
+    /// Dropping this wakes up the thread and stops it.
+    stop: mpsc::Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl Heartbeat {
+    /// Starts the thread, which stops by itself once `runtime` is gone.
+    pub(crate) fn start(
+        runtime: Weak<Runtime>,
+        implementation_id: &str,
+        name: &str,
+        interval: Duration,
+    ) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let implementation_id = implementation_id.to_string();
+        let name = name.to_string();
+        let thread = std::thread::spawn(move || {
+            let mut counter: u64 = 0;
+            // Times out until `stop` is dropped.
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let Some(runtime) = runtime.upgrade() else {
+                    return;
+                };
+                if let Err(err) = runtime.publish_variable(&implementation_id, &name, &counter) {
+                    log::error!("Heartbeat '{name}' of '{implementation_id}' failed: {err}");
+                }
+                counter = counter.wrapping_add(1);
+            }
+        });
+        Self { stop, thread }
+    }
+
+    /// Stops the thread and waits for it to finish, unless this is the thread itself: It may drop
+    /// the last reference to the `Runtime`, which then stops its heartbeats.
+    pub(crate) fn stop(self) {
+        drop(self.stop);
+        if self.thread.thread().id() != std::thread::current().id() {
+            // A panic of the thread has nobody to report to.
+            let _ = self.thread.join();
+        }
+    }
+}
@@ -1,70 +1,396 @@
-mod schema;
+#[cfg(feature = "async")]
+mod async_module;
+mod cancellation;
+mod concurrency;
+mod conf;
+mod handlers;
+mod heartbeat;
+mod logger;
+mod metrics;
+mod progress;
+mod publishing;
+mod retry;
+mod serialization;
+#[cfg(feature = "session")]
+pub mod session;
+mod subscription;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod validation;
+mod wiring;
 
 use argh::FromArgs;
 use serde::de::DeserializeOwned;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock, Weak};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
+#[cfg(feature = "async")]
+pub use async_module::{AsyncModule, AsyncSubscriber, CommandFuture};
+pub use cancellation::{CancellationToken, Cancelled};
+pub use concurrency::OverloadPolicy;
+// Generating code does not need the C++ libraries, so it lives in its own crate that build scripts
+// can depend on.
+pub use everestrs_build::{codegen, schema};
+pub use everestrs_macros::subscriber;
+pub use ffi::Module as RawModule;
+pub use handlers::{CommandClosure, VariableClosure};
+pub use logger::Logger;
+pub use metrics::Metrics;
+pub use progress::Progress;
+pub use retry::RetryPolicy;
+pub use serialization::SerializationOptions;
+pub use subscription::SubscriptionHandle;
+pub use validation::{InterfaceReport, RequirementReport, ValidationReport};
+pub use wiring::{wire_manifest, Registrar};
+
+// Re-exported so that generated code does not require users to depend on the exact same versions.
+pub use serde;
+pub use serde_json;
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("missing argument to command call: '{0}'")]
     MissingArgument(&'static str),
-    #[error("invalid argument to command call: '{0}'")]
-    InvalidArgument(&'static str),
+    /// The argument with the given name did not deserialize, the second field says why.
+    #[error("invalid argument to command call: '{0}': {1}")]
+    InvalidArgument(&'static str, String),
+    #[error("invalid commandline: {0}")]
+    InvalidCommandline(String),
+    #[error("could not create the EVerest module: {0}")]
+    ModuleCreation(String),
+    #[error("the config entry '{key}' is not set and has no default")]
+    MissingConfig { key: String },
+    #[error("the interface '{name}' is not installed")]
+    UnknownInterface { name: String },
+    #[error("this module does not provide an implementation '{implementation_id}'")]
+    UnknownImplementation { implementation_id: String },
+    #[error("this module has no requirement '{requirement_id}'")]
+    UnknownRequirement { requirement_id: String },
+    #[error("the interface of '{implementation_id}' has no variable '{name}'")]
+    UnknownVariable {
+        implementation_id: String,
+        name: String,
+    },
+    #[error("could not deserialize JSON: {context}")]
+    Deserialization { context: String },
+    #[error("command call failed: {0}")]
+    CommandFailed(String),
+    /// A handler panicked, the field is the panic message.
+    #[error("the handler panicked: {0}")]
+    HandlerPanicked(String),
+    #[error("no handler for command '{name}' on '{implementation_id}'")]
+    UnhandledCommand {
+        implementation_id: String,
+        name: String,
+    },
+    /// A command handler failed in an expected way, i.e. "connector busy". Returned from
+    /// `Subscriber::handle_command`, this is sent to the caller, whose `call_command` returns
+    /// it. All other errors of a handler reach the caller with `type_` set to `"Internal"`.
+    ///
+    /// The error travels in the result wrapped in an object, but is not validated against the
+    /// result schema of the command.
+    #[error("command failed with {type_}: {message}")]
+    Command { type_: String, message: String },
+    #[error("timeout while waiting for the command result")]
+    Timeout,
+    #[error("publishing variable failed: {0}")]
+    PublishFailed(String),
+    /// A published value or a received command result does not match its definition in the
+    /// interface. Only checked in debug builds, see `Runtime::publish_variable` and
+    /// `Runtime::call_command`.
+    #[error("value does not match the interface: {0}")]
+    SchemaViolation(String),
+    #[error("the subscriber has already been set")]
+    SubscriberAlreadySet,
+    /// The subscriber passed to `Runtime::replace_subscriber` does not provide a command that the
+    /// subscriber it replaces provides.
+    #[error("the new subscriber does not provide the command '{name}' on '{implementation_id}'")]
+    SubscriberMismatch {
+        implementation_id: String,
+        name: String,
+    },
+    #[error("the metrics have already been set")]
+    MetricsAlreadySet,
+    #[error("the variable tap has already been set")]
+    VariableTapAlreadySet,
+    #[error("no subscriber is set")]
+    NoSubscriber,
+    /// The subscriber was only referenced weakly and has been dropped.
+    #[error("the subscriber has been dropped")]
+    SubscriberDropped,
+    /// A value could not be serialized, i.e. because it is a map with non-string keys.
+    #[error("could not serialize to JSON: {0}")]
+    Serialization(String),
+    /// The C++ side failed in a way that does not depend on the arguments, `op` names the call.
+    #[error("the C++ call '{op}' failed")]
+    Ffi { op: &'static str },
+    #[error("the runtime has already been initialized")]
+    AlreadyInitialized,
+    #[error("the manifest does not set 'enable_external_mqtt'")]
+    ExternalMqttDisabled,
+    /// A command arrived while `RuntimeBuilder::max_concurrent_commands` were already running
+    /// and the `OverloadPolicy` is `Reject`. Reaches the caller with `type_` set to
+    /// `"Overloaded"`.
+    #[error("too many commands are running at the same time")]
+    Overloaded,
+    /// `source` annotated with where it happened, see [`ResultExt::context`].
+    #[error("{context}: {source}")]
+    Context {
+        context: &'static str,
+        source: Box<Error>,
+    },
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Deserialization {
+            context: err.to_string(),
+        }
+    }
+}
+
+impl Error {
+    /// Turns an error returned by `Subscriber::handle_command` into the one sent to the caller.
+    fn into_command_error(self) -> CommandError {
+        match self {
+            Error::Command { type_, message } => CommandError { type_, message },
+            Error::Overloaded => CommandError {
+                type_: "Overloaded".to_string(),
+                message: Error::Overloaded.to_string(),
+            },
+            Error::Context { context, source } => {
+                let mut command_error = source.into_command_error();
+                command_error.message = format!("{context}: {}", command_error.message);
+                command_error
+            }
+            other => CommandError {
+                type_: "Internal".to_string(),
+                message: other.to_string(),
+            },
+        }
+    }
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+/// Annotates the errors of handlers, i.e.
+/// `serde_json::from_value(value).context("parsing the limits")?`.
+pub trait ResultExt<T> {
+    /// Wraps the error in `Error::Context`. An `Error::Command` still reaches the caller of the
+    /// command with its type, the context is prepended to its message.
+    fn context(self, context: &'static str) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for ::std::result::Result<T, E> {
+    fn context(self, context: &'static str) -> Result<T> {
+        self.map_err(|err| Error::Context {
+            context,
+            source: Box::new(err.into()),
+        })
+    }
+}
+
 #[cxx::bridge]
 mod ffi {
-    struct CommandMeta {
+    /// A command of one of our implementations.
+    struct CommandRef {
         implementation_id: String,
         name: String,
     }
 
+    /// A variable of one connection of one of our requirements.
+    struct VariableRef {
+        requirement_id: String,
+        name: String,
+        index: usize,
+    }
+
+    /// The result of a command: Either the result of a successful call or, if `is_error` is set, a
+    /// command error in the form `{"type": ..., "message": ...}`.
+    struct CommandResult {
+        is_error: bool,
+        data: JsonBlob,
+    }
+
     extern "Rust" {
         type Runtime;
-        fn handle_command(self: &Runtime, meta: &CommandMeta, json: JsonBlob) -> JsonBlob;
+        fn handle_command(self: &Runtime, command: &CommandRef, json: JsonBlob) -> CommandResult;
+        fn handle_variable(self: &Runtime, variable: &VariableRef, json: JsonBlob);
         fn on_ready(&self);
+        fn on_requirement_ready(&self, requirement_id: &str, index: usize);
+        fn on_disconnected(&self);
+        fn on_reconnected(&self);
+        fn handle_external_mqtt(&self, handler_id: usize, data: &[u8]);
     }
 
     struct JsonBlob {
         data: Vec<u8>,
     }
 
+    /// The severities of EVerest's logging.
+    enum LogLevel {
+        Verbose,
+        Debug,
+        Info,
+        Warning,
+        Error,
+        Critical,
+    }
+
     unsafe extern "C++" {
         include!("everestrs_sys/everestrs_sys.hpp");
 
         type Module;
-        fn create_module(module_id: &str, prefix: &str, conf: &str) -> UniquePtr<Module>;
+
+        /// Logs `message` through EVerest's logging. Works without a `Module`.
+        fn log_message(level: LogLevel, message: &str);
+
+        /// Creates the module. Exceptions thrown by the C++ side (i.e. because the config file
+        /// could not be read) are returned as errors.
+        fn create_module(module_id: &str, prefix: &str, conf: &str) -> Result<UniquePtr<Module>>;
+
+        /// Returns the module manifest.
+        fn get_manifest(self: &Module) -> JsonBlob;
 
         /// Connects to the message broker and launches the main everest thread to push work
-        /// forward. Returns the module manifest.
-        fn initialize(self: Pin<&mut Module>) -> JsonBlob;
+        /// forward. A lost connection is reported to `rt`, while the everest thread reconnects.
+        fn initialize(self: &Module, rt: &Runtime);
+
+        /// Disconnects from the message broker and joins the main everest thread. Calling this
+        /// more than once is a no-op.
+        fn shutdown(self: &Module);
+
+        /// Blocks until the process receives SIGINT or SIGTERM or `shutdown` is called. Returns
+        /// right away if the module is not running.
+        fn wait_for_shutdown_signal(self: &Module);
+
+        /// Returns the `config` block of this module from the config yaml, with the defaults from
+        /// the manifest applied.
+        fn get_module_config(self: &Module) -> JsonBlob;
 
         /// Returns the interface definition.
         fn get_interface(self: &Module, interface_name: &str) -> JsonBlob;
 
-        /// Registers the callback of the `GenericModule` to be called and calls
-        /// `Everest::Module::signal_ready`.
+        /// Registers `on_ready` to be called back once EVerest sends the global ready signal and
+        /// calls `Everest::Module::signal_ready`. Must only be called after all commands have been
+        /// provided and all variables have been subscribed to.
         fn signal_ready(self: &Module, rt: &Runtime);
 
-        /// Informs the runtime that we implement the command described in `meta` and registers the
-        /// `handle_command` method from the `GenericModule` as the handler.
-        fn provide_command(self: &Module, rt: &Runtime, meta: &CommandMeta);
+        /// Informs the runtime that we implement `command` and registers the `handle_command`
+        /// method from the `Subscriber` as the handler.
+        fn provide_command(self: &Module, rt: &Runtime, command: &CommandRef);
+
+        /// Subscribes to `variable` and registers the `handle_variable` method from the
+        /// `Subscriber` as the handler. Returns an id for `unsubscribe_variable`.
+        fn subscribe_variable(self: &Module, rt: &Runtime, variable: &VariableRef) -> usize;
+
+        /// Removes the subscription `subscription_id`. Unknown ids are ignored.
+        fn unsubscribe_variable(self: &Module, subscription_id: usize);
+
+        /// Returns the number of modules connected to the requirement `requirement_id` in the
+        /// config, which is between its `min_connections` and `max_connections`.
+        fn get_connection_count(self: &Module, requirement_id: &str) -> usize;
+
+        /// Calls the command `name` on the module fulfilling the requirement `requirement_id`
+        /// and blocks until the result arrives.
+        fn call_command(
+            self: &Module,
+            requirement_id: &str,
+            index: usize,
+            name: &str,
+            args: JsonBlob,
+        ) -> Result<CommandResult>;
+
+        /// Like `call_command`, but stops waiting after `timeout_ms` and sets `timed_out`. The
+        /// call itself keeps running in the background until the C++ side gives up on it. Fails
+        /// once `shutdown` was called.
+        fn call_command_with_timeout(
+            self: &Module,
+            requirement_id: &str,
+            index: usize,
+            name: &str,
+            args: JsonBlob,
+            timeout_ms: u64,
+            timed_out: &mut bool,
+        ) -> Result<CommandResult>;
+
+        /// Returns the value of the variable `name` on connection `index` of the requirement
+        /// `requirement_id` that the broker retained, or an empty blob if there is none.
+        fn get_latest_variable(
+            self: &Module,
+            requirement_id: &str,
+            index: usize,
+            name: &str,
+        ) -> Result<JsonBlob>;
+
+        /// Publishes the JSON in `value` as the variable `name` of our implementation
+        /// `implementation_id`. `value` is only borrowed, so it is not copied on the way to C++.
+        fn publish_variable(
+            self: &Module,
+            implementation_id: &str,
+            name: &str,
+            value: &[u8],
+        ) -> Result<()>;
+
+        /// Like `publish_variable`, but the JSON in `value` is sent as it is formatted instead of
+        /// being re-encoded by libframework.
+        fn publish_variable_formatted(
+            self: &Module,
+            implementation_id: &str,
+            name: &str,
+            value: &[u8],
+        ) -> Result<()>;
+
+        /// Whether telemetry is enabled for this module in the config.
+        fn is_telemetry_enabled(self: &Module) -> bool;
+
+        /// Publishes the JSON in `data` on the telemetry topic `category`.
+        fn publish_telemetry(self: &Module, category: &str, data: &[u8]);
+
+        /// Publishes `data` on `topic` below the external MQTT prefix.
+        fn publish_external_mqtt(self: &Module, topic: &str, data: &[u8]) -> Result<()>;
+
+        /// Subscribes to `topic` below the external MQTT prefix, its messages are passed to `rt`
+        /// with `handler_id`.
+        fn subscribe_external_mqtt(
+            self: &Module,
+            rt: &Runtime,
+            topic: &str,
+            handler_id: usize,
+        ) -> Result<()>;
     }
 }
 
+// SAFETY: All methods of the C++ `Module` that can be called concurrently only call into
+// `Everest::Everest`, which synchronizes internally, or guard their own state.
+unsafe impl Send for ffi::Module {}
+unsafe impl Sync for ffi::Module {}
+
+// The Runtime is shared between the everest thread and the threads of the module, so all of its
+// state has to be synchronized. This stops compiling if a field breaks that.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Runtime>();
+};
+
 impl ffi::JsonBlob {
     fn as_bytes(&self) -> &[u8] {
         &self.data
     }
 
-    fn deserialize<T: DeserializeOwned>(self) -> T {
-        // TODO(hrapp): Error handling
-        serde_json::from_slice(self.as_bytes()).unwrap()
+    fn deserialize<T: DeserializeOwned>(self) -> Result<T> {
+        self.deserialize_ref()
+    }
+
+    /// Like `deserialize`, but `T` may borrow strings from the blob instead of copying them.
+    fn deserialize_ref<'a, T: Deserialize<'a>>(&'a self) -> Result<T> {
+        deserialize_slice(self.as_bytes())
     }
 
     fn from_vec(data: Vec<u8>) -> Self {
@@ -72,17 +398,205 @@ impl ffi::JsonBlob {
     }
 }
 
+impl ffi::CommandResult {
+    /// Encodes what a command handler returned for the caller. An error is sent wrapped in an object
+    /// as the result, which libframework does not validate against the result schema.
+    fn from_handler(result: Result<serde_json::Value>) -> Self {
+        match result {
+            Ok(value) => Self {
+                is_error: false,
+                data: ffi::JsonBlob::from_vec(
+                    serde_json::to_vec(&value).expect("Serialization of data cannot fail."),
+                ),
+            },
+            Err(err) => Self {
+                is_error: true,
+                data: ffi::JsonBlob::from_vec(
+                    serde_json::to_vec(&err.into_command_error())
+                        .expect("Serialization of data cannot fail."),
+                ),
+            },
+        }
+    }
+
+    /// Decodes the result of a call into `R`, or its error if the handler failed.
+    fn into_result<R: DeserializeOwned>(self) -> Result<R> {
+        if self.is_error {
+            return Err(self.into_error());
+        }
+        self.data.deserialize()
+    }
+
+    /// Decodes the error of a failed call, `is_error` must be set.
+    fn into_error(self) -> Error {
+        match self.data.deserialize::<CommandError>() {
+            Ok(CommandError { type_, message }) => Error::Command { type_, message },
+            Err(err) => err,
+        }
+    }
+}
+
+/// Sees every received variable update, see [`Runtime::set_variable_tap`].
+pub type VariableTap = Box<dyn Fn(&str, &str, &serde_json::Value) + Send + Sync>;
+
+/// Handles the messages of an external MQTT topic, see [`Runtime::mqtt_subscribe`].
+type ExternalMqttHandler = Box<dyn Fn(&[u8]) + Send + Sync>;
+
+/// How the `Runtime` refers to its `Subscriber`, see `Runtime::set_subscriber` and
+/// `Runtime::set_owned_subscriber`.
+enum SubscriberRef {
+    Weak(Weak<dyn Subscriber>),
+    Strong(Arc<dyn Subscriber>),
+}
+
+/// What an interface declares about a variable, see [`Runtime::variable_metadata`].
+#[derive(Debug, Clone, Copy)]
+pub struct VariableMetadata<'a> {
+    pub unit: Option<&'a str>,
+    pub description: Option<&'a str>,
+    /// The type of the value as a JSON schema.
+    pub type_: &'a schema::interface::Argument,
+}
+
+/// Which implementations are ready, see `RuntimeBuilder::ready_per_implementation`.
+#[derive(Default)]
+struct ImplementationsReady {
+    ready: BTreeSet<String>,
+    /// Whether `initialize` is done, the module cannot signal that it is ready before.
+    initialized: bool,
+    signalled: bool,
+}
+
+/// The wire format of `Error::Command`.
+#[derive(Serialize, serde::Deserialize)]
+struct CommandError {
+    #[serde(rename = "type")]
+    type_: String,
+    message: String,
+}
+
+/// Deserializes the JSON in `data` into `T`. On failure, the error names `T` and contains the
+/// message of serde, which includes the line and column of the offending value.
+/// Parses a payload. With the `arbitrary-precision` feature, numbers reach `T` as their exact text,
+/// see `serde_json::Number`.
+fn deserialize_slice<'a, T: Deserialize<'a>>(data: &'a [u8]) -> Result<T> {
+    serde_json::from_slice(data).map_err(|e| Error::Deserialization {
+        context: format!("`{}`: {e}", std::any::type_name::<T>()),
+    })
+}
+
+/// Serializes a payload. With the `arbitrary-precision` feature, numbers are written as the text
+/// they were parsed from or given as, not as `f64`.
+fn serialize<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    serde_json::to_vec(value).map_err(|e| Error::Serialization(e.to_string()))
+}
+
+/// Returns the message of a panic caught with `catch_unwind`.
+fn panic_message(panic: &Box<dyn std::any::Any + Send>) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Calls `f`, which calls a handler of the module, and turns a panic into
+/// `Error::HandlerPanicked`. Unwinding into C++ would abort the process.
+fn catch_panic<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+        .unwrap_or_else(|panic| Err(Error::HandlerPanicked(panic_message(&panic))))
+}
+
+/// Fails with `Error::UnknownImplementation` if `manifest` does not provide `implementation_id`.
+/// Only ours are provided, but a bug on the C++ side must not reach the match of the subscriber,
+/// which likely panics for ids it does not know.
+fn check_provided(manifest: &schema::Manifest, implementation_id: &str) -> Result<()> {
+    if manifest.provides.contains_key(implementation_id) {
+        Ok(())
+    } else {
+        Err(Error::UnknownImplementation {
+            implementation_id: implementation_id.to_string(),
+        })
+    }
+}
+
+/// Calls `f`, which calls into the code of the module, and logs a panic as a failure of `what`.
+/// Unwinding into C++ would abort the process, so every callback from C++ must either go through
+/// this or catch panics itself.
+fn log_panic(what: &str, f: impl FnOnce()) {
+    if let Err(panic) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+        log::error!("{what} panicked: {}", panic_message(&panic));
+    }
+}
+
+/// Returns the argument `name` of a command from the `parameters` passed to
+/// `Subscriber::handle_command`. Fails with `Error::MissingArgument` if it is not there and with
+/// `Error::InvalidArgument` if it does not deserialize into `T`.
+pub fn required_arg<T: DeserializeOwned>(
+    parameters: &HashMap<String, serde_json::Value>,
+    name: &'static str,
+) -> Result<T> {
+    let value = parameters.get(name).ok_or(Error::MissingArgument(name))?;
+    T::deserialize(value).map_err(|e| Error::InvalidArgument(name, e.to_string()))
+}
+
+/// Deserializes all `parameters` passed to `Subscriber::handle_command` into one struct `T`, for
+/// commands whose arguments are modelled as a single type. Fails with `Error::InvalidArgument`
+/// naming `T` and, through the message of serde, the missing field or the offending value.
+pub fn params_into<T: DeserializeOwned>(
+    parameters: HashMap<String, serde_json::Value>,
+) -> Result<T> {
+    let parameters: serde_json::Map<_, _> = parameters.into_iter().collect();
+    serde_json::from_value(serde_json::Value::Object(parameters))
+        .map_err(|e| Error::InvalidArgument(std::any::type_name::<T>(), e.to_string()))
+}
+
+/// Like [`required_arg`], but returns `None` if the argument is missing or `null`.
+pub fn optional_arg<T: DeserializeOwned>(
+    parameters: &HashMap<String, serde_json::Value>,
+    name: &'static str,
+) -> Result<Option<T>> {
+    match parameters.get(name) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(value) => T::deserialize(value)
+            .map(Some)
+            .map_err(|e| Error::InvalidArgument(name, e.to_string())),
+    }
+}
+
+/// Converts `value` into a `serde_json::Value`, for code that deals with values of any type.
+/// Fails with `Error::Serialization`, i.e. for maps with non-string keys.
+pub fn to_value<T: Serialize>(value: &T) -> Result<serde_json::Value> {
+    serde_json::to_value(value).map_err(|e| Error::Serialization(e.to_string()))
+}
+
+/// Deserializes the raw JSON passed to `Subscriber::handle_variable_raw` into `T`, which may
+/// borrow from `data`, i.e. have `&str` fields, so that reading a few strings of a frequently
+/// updated variable does not allocate. Fails with `Error::Deserialization` naming `T`, strings
+/// with escapes cannot be borrowed and fail as well unless their field is a `Cow<str>`.
+pub fn from_slice<'a, T: Deserialize<'a>>(data: &'a [u8]) -> Result<T> {
+    deserialize_slice(data)
+}
+
+/// Converts a `serde_json::Value` into `T`, the counterpart of [`to_value`]. Fails with
+/// `Error::Deserialization` naming `T`.
+pub fn from_value<T: DeserializeOwned>(value: serde_json::Value) -> Result<T> {
+    serde_json::from_value(value).map_err(|e| Error::Deserialization {
+        context: format!("`{}`: {e}", std::any::type_name::<T>()),
+    })
+}
+
 #[derive(FromArgs, Debug)]
 /// An everest Node.
-struct Args {
+pub struct Args {
     /// prefix of installation.
     #[argh(option)]
-    #[allow(unused)]
     pub prefix: PathBuf,
 
     /// configuration yml that we are running.
     #[argh(option)]
-    #[allow(unused)]
     pub conf: PathBuf,
 
     /// module name for us.
@@ -90,13 +604,66 @@ struct Args {
     pub module: String,
 }
 
+impl Args {
+    /// Parses the commandline of the current process. Unlike `argh::from_env` this does not exit
+    /// the process on invalid input (or `--help`), but returns the message argh would have printed
+    /// as an `Error::InvalidCommandline`.
+    pub fn try_from_env() -> Result<Self> {
+        let strings: Vec<String> = std::env::args_os()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect();
+        let cmd = strings
+            .first()
+            .and_then(|s| std::path::Path::new(s).file_name())
+            .and_then(|s| s.to_str())
+            .unwrap_or("everestrs");
+        let args: Vec<&str> = strings.iter().skip(1).map(String::as_str).collect();
+        Self::from_args(&[cmd], &args).map_err(|e| Error::InvalidCommandline(e.output))
+    }
+}
+
+/// The parts of the [`Runtime`] that the generated clients and publishers use. They are generic
+/// over it, so that module code built on them can run against a `testing::MockRuntime` in tests.
+pub trait RuntimeApi {
+    /// See [`Runtime::call_command`].
+    fn call_command<T: Serialize, R: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+        args: &T,
+    ) -> Result<R>;
+
+    /// See [`Runtime::call_command_noargs`].
+    fn call_command_noargs<R: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+    ) -> Result<R> {
+        self.call_command(requirement_id, index, name, &serde_json::Map::new())
+    }
+
+    /// See [`Runtime::publish_variable`].
+    fn publish_variable<T: Serialize>(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        value: &T,
+    ) -> Result<()>;
+}
+
 /// Implements the handling of commands & variables, but has no specific information about the
 /// details of the current module, i.e. it deals with JSON blobs and strings as command names. Code
 /// generation is used to build the concrete, strongly typed abstractions that are then used by
 /// final implementors.
-pub trait GenericModule: Sync {
+pub trait Subscriber: Send + Sync {
     /// Handler for the command `name` on `implementation_id` with the given `parameters`. The return value
     /// will be returned as the result of the call.
+    ///
+    /// All commands of the manifest are provided, so this should return `Error::UnhandledCommand`
+    /// for the ones it does not know. The runtime logs that error, and turns a panic of the
+    /// handler into `Error::HandlerPanicked`, both of which reach the caller as errors.
     fn handle_command(
         &self,
         implementation_id: &str,
@@ -104,73 +671,1911 @@ pub trait GenericModule: Sync {
         parameters: HashMap<String, serde_json::Value>,
     ) -> Result<serde_json::Value>;
 
-    fn on_ready(&self) {}
+    /// Handler for updates of the variable `name` on the connection `index` of the requirement
+    /// `implementation_id`. Errors are passed to `on_error`, they do not interrupt the processing
+    /// of further updates. Neither do panics, which are logged and passed on as
+    /// `Error::HandlerPanicked`.
+    ///
+    /// Every subscription carries its requirement id, so variables of the same name on different
+    /// requirements arrive with their own `implementation_id` and must be matched on both.
+    fn handle_variable(
+        &self,
+        _implementation_id: &str,
+        _index: usize,
+        _name: &str,
+        _value: serde_json::Value,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Like `handle_variable`, but gets the value as the raw JSON it was received as. The default
+    /// parses it into a `serde_json::Value` and calls `handle_variable`. Override this to parse
+    /// large values directly into their final type instead, or with [`from_slice`] into a type
+    /// that borrows from `data`.
+    fn handle_variable_raw(
+        &self,
+        implementation_id: &str,
+        index: usize,
+        name: &str,
+        data: &[u8],
+    ) -> Result<()> {
+        self.handle_variable(implementation_id, index, name, deserialize_slice(data)?)
+    }
+
+    /// Decides if the runtime subscribes to the variable `name` of the requirement
+    /// `implementation_id`. Variables for which this returns false never reach `handle_variable`.
+    /// By default, all variables of all requirements are subscribed to.
+    fn subscribes_to(&self, _implementation_id: &str, _name: &str) -> bool {
+        true
+    }
+
+    /// Decides if the runtime provides the command `name` of our implementation
+    /// `implementation_id` to EVerest, i.e. to only implement some commands depending on the
+    /// config. Commands for which this returns false never reach `handle_command`, calls to them
+    /// time out on the caller's side. By default, all commands of all implementations are
+    /// provided.
+    fn provides_command(&self, _implementation_id: &str, _name: &str) -> bool {
+        true
+    }
+
+    /// Called with the errors of handlers that have no caller to report them to, i.e. of
+    /// `handle_variable`.
+    fn on_error(&self, _err: Error) {}
+
+    /// Called instead of `on_error` when `handle_variable_raw` fails with
+    /// `Error::Deserialization`, which it does if the value of the variable `name` on the
+    /// connection `index` of the requirement `implementation_id` does not deserialize into the
+    /// expected type, i.e. because the other module uses a newer interface. `raw` is the JSON as
+    /// received, so that modules that need to keep going can fall back to a
+    /// `serde_json::Value` or an older type. The default logs a warning, drops the value and
+    /// passes `err` on to `on_error`.
+    fn handle_variable_undecoded(
+        &self,
+        implementation_id: &str,
+        index: usize,
+        name: &str,
+        _raw: &[u8],
+        err: Error,
+    ) {
+        log::warn!(
+            "Dropping variable '{name}' of '{implementation_id}' from connection {index}: {err}"
+        );
+        self.on_error(err);
+    }
+
+    /// Called once the whole EVerest config is up: EVerest only sends the ready signal after every
+    /// module, including this one, provided all of its commands and subscribed to all of its
+    /// variables. So from here on, commands can be called and published variables reach all
+    /// subscribers. Handlers can already be called before this.
+    ///
+    /// This runs on the everest thread and can happen before [`Runtime::initialize`] returns.
+    /// Modules that decide what to watch only now subscribe with [`Runtime::subscribe`] here,
+    /// see [`RuntimeBuilder::manual_subscriptions`].
+    fn on_ready(&self, _runtime: &Runtime) {}
+
+    /// Called once the module on connection `index` of our requirement `requirement_id` signalled
+    /// that it is ready, i.e. it provides its commands from now on. This allows to sequence the
+    /// startup, e.g. to only start polling a meter once it is up.
+    ///
+    /// Every connection is reported exactly once. The ready signal of a module is not retained,
+    /// so if it signalled before we connected, its connection is reported right before
+    /// `on_ready`, which implies that all modules are ready. Requirements without connections
+    /// (see [`Runtime::connection_count`]) are never reported. The connections themselves are
+    /// fixed by the config when the module is created, none appear or disappear later.
+    fn on_requirement_ready(&self, _requirement_id: &str, _index: usize) {}
+
+    /// Called when the connection to the MQTT broker was lost. The runtime keeps trying to
+    /// reconnect. Until then, published variables are buffered and sent after reconnecting, while
+    /// command calls fail once they time out.
+    fn on_disconnected(&self) {}
+
+    /// Called when the connection to the MQTT broker was restored. All commands and variable
+    /// subscriptions are live again and the variables published in the meantime have been sent.
+    fn on_reconnected(&self) {}
 }
 
+/// The connection of a module to EVerest.
+///
+/// Its lifecycle has three steps: It is created through a [`RuntimeBuilder`] (or
+/// [`Runtime::from_commandline`]), the [`Subscriber`] handling commands and variables is attached
+/// through [`Runtime::set_subscriber`] and [`Runtime::initialize`] finally connects to EVerest.
 pub struct Runtime {
-    // There are two subtleties here:
-    // 1. We are handing out pointers to `module_impl` to `cpp_module` for callbacks. The pointers
-    //    must must stay valid for as long as `cpp_module` is alive. Hence `module_impl` must never
-    //    move in memory. Rust can model this through the Pin concept which upholds this guarantee.
-    //    We use a Box to put the object on the heap.
-    // 2. For the same reason, `module_impl` should outlive `cpp_module`, hence should be dropped
-    //    after it. Rust drops fields in declaration order, hence `cpp_module` should come before
-    //    `module_impl` in this struct.
+    // We are handing out pointers to `self` to `cpp_module` for callbacks. The pointers must stay
+    // valid for as long as `cpp_module` is alive. Hence the Runtime must never move in memory,
+    // which is why it is only handed out as a `Pin<Arc<Runtime>>`, and `cpp_module` is shut down
+    // in `drop`.
+    //
+    // The subscriber usually holds on to the Runtime to call commands and publish variables, so
+    // we usually only keep a weak reference to it to not create a cycle. Callbacks after the
+    // subscriber went away are dropped.
     cpp_module: cxx::UniquePtr<ffi::Module>,
-    module_impl: Pin<Box<dyn GenericModule>>,
+    /// Ourselves, for what may outlive a borrow of the `Runtime` but must not keep it alive, i.e.
+    /// a `SubscriptionHandle` or the thread of a heartbeat.
+    this: Weak<Runtime>,
+    module_id: String,
+    subscriber: RwLock<Option<SubscriberRef>>,
+    /// The subscribers set with `set_subscriber_for`, by implementation or requirement id. They
+    /// take precedence over `subscriber`.
+    subscribers_by_id: RwLock<HashMap<String, Arc<dyn Subscriber>>>,
+    /// The subscriber installed by `on_command` and `on_variable`, if they were used.
+    handlers: OnceLock<Arc<handlers::Handlers>>,
+    metrics: OnceLock<Box<dyn Metrics>>,
+    variable_tap: OnceLock<VariableTap>,
+    /// The last value sent with `publish_variable_if_changed`.
+    last_published: publishing::LastPublished,
+    /// The handlers of `mqtt_subscribe`, their index is the id the C++ side calls back with.
+    external_mqtt_handlers: RwLock<Vec<Arc<ExternalMqttHandler>>>,
+    /// The threads of `start_heartbeat`, by implementation id and variable name.
+    heartbeats: Mutex<HashMap<(String, String), heartbeat::Heartbeat>>,
+    /// Cancelled when we shut down, see `cancellation_token`.
+    cancellation: CancellationToken,
+    initialized: AtomicBool,
+    /// Becomes ready once EVerest signalled that all modules are ready. Until then, publishes are
+    /// queued and only sent in `on_ready`.
+    publishes: publishing::PublishQueue,
+    /// The connections of our requirements that have been reported as ready to the subscriber.
+    ready_requirements: Mutex<BTreeSet<(String, usize)>>,
+    telemetry_enabled: bool,
+    /// The number of commands provided and variables subscribed to in `initialize`.
+    provided_commands: AtomicUsize,
+    subscribed_variables: AtomicUsize,
+    /// Whether `initialize` leaves subscribing to the subscriber, see
+    /// `RuntimeBuilder::manual_subscriptions`.
+    manual_subscriptions: bool,
+    /// Whether `initialize` logs what it wired, see `RuntimeBuilder::log_topology`.
+    log_topology: bool,
+    /// Set if the implementations signal that they are ready one by one, see
+    /// `RuntimeBuilder::ready_per_implementation`.
+    implementations_ready: Option<Mutex<ImplementationsReady>>,
+    /// See `RuntimeBuilder::max_concurrent_commands`.
+    command_limit: Option<concurrency::CommandLimit>,
+    /// See `RuntimeBuilder::serialization`.
+    serialization: SerializationOptions,
+    manifest: schema::Manifest,
+    /// The definitions of all interfaces that we provide or require, by name. Interfaces of
+    /// requirements without connections are missing.
+    interfaces: BTreeMap<String, schema::Interface>,
 }
 
 impl Runtime {
+    fn subscriber(&self) -> Option<Arc<dyn Subscriber>> {
+        self.try_subscriber().ok()
+    }
+
+    /// Returns the subscriber for the implementation or requirement `id`.
+    fn subscriber_for(&self, id: &str) -> Result<Arc<dyn Subscriber>> {
+        match self.subscribers_by_id.read().unwrap().get(id) {
+            Some(subscriber) => Ok(Arc::clone(subscriber)),
+            None => self.try_subscriber(),
+        }
+    }
+
+    fn try_subscriber(&self) -> Result<Arc<dyn Subscriber>> {
+        match self.subscriber.read().unwrap().as_ref() {
+            None => Err(Error::NoSubscriber),
+            Some(SubscriberRef::Weak(subscriber)) => {
+                subscriber.upgrade().ok_or(Error::SubscriberDropped)
+            }
+            Some(SubscriberRef::Strong(subscriber)) => Ok(Arc::clone(subscriber)),
+        }
+    }
+
     fn on_ready(&self) {
-        self.module_impl.on_ready();
+        let subscriber = self.subscriber();
+        {
+            let result = self.publishes.set_ready(|implementation_id, name, data| {
+                self.send_variable(implementation_id, name, data)
+            });
+            if let (Err(err), Some(subscriber)) = (result, &subscriber) {
+                log_panic("Subscriber::on_error", || subscriber.on_error(err));
+            }
+        }
+        // The global ready signal implies that all our requirements are ready, too.
+        for requirement_id in self.manifest.requires.keys() {
+            for index in 0..self.connection_count(requirement_id) {
+                self.on_requirement_ready(requirement_id, index);
+            }
+        }
+        if let Some(subscriber) = subscriber {
+            log_panic("Subscriber::on_ready", || subscriber.on_ready(self));
+        }
     }
 
-    fn handle_command(&self, meta: &ffi::CommandMeta, json: ffi::JsonBlob) -> ffi::JsonBlob {
-        let blob = self
-            .module_impl
-            .handle_command(&meta.implementation_id, &meta.name, json.deserialize())
-            .unwrap();
-        ffi::JsonBlob::from_vec(serde_json::to_vec(&blob).unwrap())
+    fn on_requirement_ready(&self, requirement_id: &str, index: usize) {
+        let newly_ready = self
+            .ready_requirements
+            .lock()
+            .unwrap()
+            .insert((requirement_id.to_string(), index));
+        if !newly_ready {
+            return;
+        }
+        if let Ok(subscriber) = self.subscriber_for(requirement_id) {
+            log_panic("Subscriber::on_requirement_ready", || {
+                subscriber.on_requirement_ready(requirement_id, index)
+            });
+        }
     }
 
-    // TODO(hrapp): This function could use some error handling.
-    pub fn from_commandline<T: GenericModule + 'static>(module_impl: T) -> Self {
-        let args: Args = argh::from_env();
-        let mut cpp_module = ffi::create_module(
-            &args.module,
-            &args.prefix.to_string_lossy(),
-            &args.conf.to_string_lossy(),
-        );
-        let manifest_json = cpp_module.as_mut().unwrap().initialize();
-        let manifest: schema::Manifest = manifest_json.deserialize();
-        let module = Self {
-            cpp_module,
-            module_impl: Box::pin(module_impl),
+    fn on_disconnected(&self) {
+        if let Some(subscriber) = self.subscriber() {
+            log_panic("Subscriber::on_disconnected", || {
+                subscriber.on_disconnected()
+            });
+        }
+    }
+
+    fn on_reconnected(&self) {
+        if let Some(subscriber) = self.subscriber() {
+            log_panic("Subscriber::on_reconnected", || subscriber.on_reconnected());
+        }
+    }
+
+    fn handle_external_mqtt(&self, handler_id: usize, data: &[u8]) {
+        // Cloned so that the handler can subscribe to further topics.
+        let handler = self
+            .external_mqtt_handlers
+            .read()
+            .unwrap()
+            .get(handler_id)
+            .cloned();
+        if let Some(handler) = handler {
+            log_panic("The external MQTT handler", || handler(data));
+        }
+    }
+
+    fn handle_command(&self, command: &ffi::CommandRef, json: ffi::JsonBlob) -> ffi::CommandResult {
+        // Callers that pass `&()` for a command without arguments send `null`.
+        let parameters = json
+            .deserialize::<Option<HashMap<String, serde_json::Value>>>()
+            .map(Option::unwrap_or_default);
+        let result = parameters.and_then(|parameters| {
+            check_provided(&self.manifest, &command.implementation_id)?;
+            let subscriber = self.subscriber_for(&command.implementation_id)?;
+            let _permit = self
+                .command_limit
+                .as_ref()
+                .map(|limit| limit.acquire())
+                .transpose()?;
+            // Handlers commonly panic in the fallback arm for commands they do not know, so the
+            // caller gets an error for it.
+            catch_panic(|| {
+                subscriber.handle_command(&command.implementation_id, &command.name, parameters)
+            })
+        });
+        if let Err(
+            err @ (Error::UnknownImplementation { .. }
+            | Error::UnhandledCommand { .. }
+            | Error::HandlerPanicked(_)
+            | Error::Overloaded),
+        ) = &result
+        {
+            log::error!(
+                "Command '{}' of '{}' failed: {err}",
+                command.name,
+                command.implementation_id
+            );
+        }
+        if let Some(metrics) = self.metrics.get() {
+            metrics.command_handled(&command.implementation_id, &command.name);
+            if result.is_err() {
+                metrics.handler_failed(&command.implementation_id, &command.name);
+            }
+        }
+        ffi::CommandResult::from_handler(result)
+    }
+
+    /// Installs [`Logger`] as the global logger of the `log` crate, so that `log::info!` and friends
+    /// end up in EVerest's logging. Fails if another logger has already been installed.
+    pub fn init_logging() -> std::result::Result<(), log::SetLoggerError> {
+        logger::init()
+    }
+
+    fn handle_variable(&self, variable: &ffi::VariableRef, json: ffi::JsonBlob) {
+        let metrics = self.metrics.get();
+        if let Some(metrics) = metrics {
+            metrics.variable_received(&variable.requirement_id, &variable.name);
+        }
+        if let Some(tap) = self.variable_tap.get() {
+            // Malformed values are reported by the subscriber below.
+            if let Ok(value) = serde_json::from_slice(json.as_bytes()) {
+                log_panic("The variable tap", || {
+                    tap(&variable.requirement_id, &variable.name, &value)
+                });
+            }
+        }
+        let Ok(subscriber) = self.subscriber_for(&variable.requirement_id) else {
+            return;
+        };
+        let result = catch_panic(|| {
+            subscriber.handle_variable_raw(
+                &variable.requirement_id,
+                variable.index,
+                &variable.name,
+                json.as_bytes(),
+            )
+        });
+        if let Err(err) = result {
+            if let Error::HandlerPanicked(_) = &err {
+                log::error!(
+                    "Variable '{}' of '{}' failed: {err}",
+                    variable.name,
+                    variable.requirement_id
+                );
+            }
+            if let Some(metrics) = metrics {
+                metrics.handler_failed(&variable.requirement_id, &variable.name);
+            }
+            if let Error::Deserialization { .. } = &err {
+                log_panic("Subscriber::handle_variable_undecoded", || {
+                    subscriber.handle_variable_undecoded(
+                        &variable.requirement_id,
+                        variable.index,
+                        &variable.name,
+                        json.as_bytes(),
+                        err,
+                    )
+                });
+            } else {
+                log_panic("Subscriber::on_error", || subscriber.on_error(err));
+            }
+        }
+    }
+
+    /// Attaches the `subscriber` that handles the commands and variables of this module. Fails
+    /// with `Error::SubscriberAlreadySet` if this has been called before, swapping the subscriber
+    /// goes through [`Runtime::replace_subscriber`].
+    ///
+    /// Only a weak reference is kept, commands arriving after the subscriber was dropped fail with
+    /// `Error::SubscriberDropped`, variables are ignored. This is the right choice if the
+    /// subscriber holds on to the `Runtime`, use [`Runtime::set_owned_subscriber`] otherwise.
+    pub fn set_subscriber(&self, subscriber: Weak<dyn Subscriber>) -> Result<()> {
+        self.attach_subscriber(SubscriberRef::Weak(subscriber))
+    }
+
+    /// Like [`Runtime::set_subscriber`], but keeps the `subscriber` alive for as long as the
+    /// `Runtime`, so it does not need to be created with `Arc::new_cyclic` or kept around by the
+    /// caller. The `subscriber` must not hold on to the `Runtime` itself, as that creates a cycle
+    /// and neither of them is ever dropped. It can still use the `Runtime` passed to
+    /// `Subscriber::on_ready`.
+    pub fn set_owned_subscriber(&self, subscriber: Arc<dyn Subscriber>) -> Result<()> {
+        self.attach_subscriber(SubscriberRef::Strong(subscriber))
+    }
+
+    /// Swaps the subscriber set with [`Runtime::set_subscriber`] or
+    /// [`Runtime::set_owned_subscriber`] for `subscriber`, i.e. after a reconfiguration, and
+    /// returns the old one, unless none was set or it has been dropped. It is returned as an
+    /// `Arc`, as an owned subscriber is dropped as soon as the `Runtime` lets go of it.
+    ///
+    /// The commands have been provided to EVerest according to `Subscriber::provides_command` of
+    /// the old subscriber and cannot be withdrawn, so this fails with `Error::SubscriberMismatch`
+    /// if `subscriber` does not provide one of them, and with `Error::SubscriberDropped` if it
+    /// has already been dropped. Commands of implementations with their own subscriber from
+    /// [`Runtime::set_subscriber_for`] are not checked. Subscribed variables stay subscribed.
+    /// Subscribers installed by [`Runtime::on_command`] cannot be replaced, closures registered
+    /// afterwards would go to the old one.
+    pub fn replace_subscriber(
+        &self,
+        subscriber: Weak<dyn Subscriber>,
+    ) -> Result<Option<Arc<dyn Subscriber>>> {
+        if self.handlers.get().is_some() {
+            return Err(Error::SubscriberAlreadySet);
+        }
+        let new = subscriber.upgrade().ok_or(Error::SubscriberDropped)?;
+        let (is_set, old) = match self.subscriber.read().unwrap().as_ref() {
+            None => (false, None),
+            Some(SubscriberRef::Weak(old)) => (true, old.upgrade()),
+            Some(SubscriberRef::Strong(old)) => (true, Some(Arc::clone(old))),
         };
+        if is_set {
+            // A dropped subscriber cannot tell which commands it provided, so all must be.
+            self.check_provides_commands(old.as_deref(), new.as_ref())?;
+        }
+        *self.subscriber.write().unwrap() = Some(SubscriberRef::Weak(subscriber));
+        Ok(old)
+    }
 
-        // Implement all commands for all of our implementations, dispatch everything to the
-        // GenericModule.
-        for (implementation_id, implementation) in manifest.provides {
-            let interface_s = module.cpp_module.get_interface(&implementation.interface);
-            let interface: schema::Interface = interface_s.deserialize();
-            for (name, _) in interface.cmds {
-                let meta = ffi::CommandMeta {
-                    implementation_id: implementation_id.clone(),
-                    name,
-                };
+    /// Fails if `new` does not provide a command of the implementations handled by the main
+    /// subscriber that `old` provides, or that is provided by default if `old` is `None`.
+    fn check_provides_commands(
+        &self,
+        old: Option<&dyn Subscriber>,
+        new: &dyn Subscriber,
+    ) -> Result<()> {
+        let subscribers = self.subscribers_by_id.read().unwrap();
+        for (implementation_id, implementation) in &self.manifest.provides {
+            if subscribers.contains_key(implementation_id) {
+                continue;
+            }
+            let Some(interface) = self.interfaces.get(&implementation.interface) else {
+                continue;
+            };
+            for name in interface.cmds.keys() {
+                let provided = old.is_none_or(|old| old.provides_command(implementation_id, name));
+                if provided && !new.provides_command(implementation_id, name) {
+                    return Err(Error::SubscriberMismatch {
+                        implementation_id: implementation_id.clone(),
+                        name: name.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Dispatches the commands of our implementation `id`, or the variables of our requirement
+    /// `id`, to `subscriber` instead of the one set with [`Runtime::set_subscriber`], so that
+    /// large modules can split their handlers into one object per implementation. Must be called
+    /// before [`Runtime::initialize`] to be asked about `Subscriber::subscribes_to`. Fails with
+    /// `Error::SubscriberAlreadySet` if `id` already has a subscriber.
+    ///
+    /// The `Runtime` keeps `subscriber` alive, so it must not hold on to the `Runtime`. Its
+    /// `on_ready`, `on_disconnected` and `on_reconnected` are not called, those only go to the
+    /// subscriber set with `set_subscriber`.
+    pub fn set_subscriber_for(&self, id: &str, subscriber: Arc<dyn Subscriber>) -> Result<()> {
+        let mut subscribers = self.subscribers_by_id.write().unwrap();
+        if subscribers.contains_key(id) {
+            return Err(Error::SubscriberAlreadySet);
+        }
+        subscribers.insert(id.to_string(), subscriber);
+        Ok(())
+    }
+
+    /// Handles the command `name` of our implementation `implementation_id` with `handler`, for
+    /// modules that do not want to implement [`Subscriber`]. The first call installs a subscriber
+    /// that routes commands and variables to the closures registered here, so this fails with
+    /// `Error::SubscriberAlreadySet` if another subscriber was set. Commands without a closure
+    /// fail with `Error::UnhandledCommand`.
+    pub fn on_command(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        handler: impl Fn(HashMap<String, serde_json::Value>) -> Result<serde_json::Value>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Result<()> {
+        self.handlers()?
+            .add_command(implementation_id, name, Box::new(handler));
+        Ok(())
+    }
+
+    /// Like [`Runtime::on_command`], but handles the variable `name` of our requirement
+    /// `requirement_id` with `handler`, which gets the connection index and the value. Only
+    /// variables with a closure are subscribed to, so this must be called before
+    /// [`Runtime::initialize`]. Errors of `handler` are logged.
+    pub fn on_variable(
+        &self,
+        requirement_id: &str,
+        name: &str,
+        handler: impl Fn(usize, serde_json::Value) -> Result<()> + Send + Sync + 'static,
+    ) -> Result<()> {
+        self.handlers()?
+            .add_variable(requirement_id, name, Box::new(handler));
+        Ok(())
+    }
+
+    fn handlers(&self) -> Result<&handlers::Handlers> {
+        let mut current = self.subscriber.write().unwrap();
+        if let Some(handlers) = self.handlers.get() {
+            return Ok(handlers);
+        }
+        if current.is_some() {
+            return Err(Error::SubscriberAlreadySet);
+        }
+        let handlers = Arc::new(handlers::Handlers::default());
+        *current = Some(SubscriberRef::Strong(handlers.clone()));
+        Ok(self.handlers.get_or_init(|| handlers))
+    }
+
+    fn attach_subscriber(&self, subscriber: SubscriberRef) -> Result<()> {
+        let mut current = self.subscriber.write().unwrap();
+        if current.is_some() {
+            return Err(Error::SubscriberAlreadySet);
+        }
+        *current = Some(subscriber);
+        Ok(())
+    }
+
+    /// Installs the sink for counters about handled and published commands and variables. Without
+    /// one, counting costs nothing. Fails with `Error::MetricsAlreadySet` if called twice.
+    pub fn set_metrics(&self, metrics: Box<dyn Metrics>) -> Result<()> {
+        self.metrics
+            .set(metrics)
+            .map_err(|_| Error::MetricsAlreadySet)
+    }
+
+    /// Installs `tap`, which sees every variable update we receive with the requirement id, the
+    /// name and the value before it is dispatched to the `Subscriber`, i.e. for recording them
+    /// to replay them later. It runs on the thread delivering the update, so it should be quick.
+    /// Without a tap, nothing is parsed for it. Fails with `Error::VariableTapAlreadySet` if
+    /// called twice.
+    pub fn set_variable_tap(&self, tap: VariableTap) -> Result<()> {
+        self.variable_tap
+            .set(tap)
+            .map_err(|_| Error::VariableTapAlreadySet)
+    }
+
+    /// Connects to EVerest, provides all commands of our implementations and subscribes to the
+    /// variables of our requirements, then signals that we are ready, unless that is left to
+    /// [`Runtime::signal_ready_for`]. Set the subscriber before, otherwise it misses commands and
+    /// variables and cannot choose its commands and variables through
+    /// `Subscriber::provides_command` and `Subscriber::subscribes_to`. Fails with
+    /// `Error::AlreadyInitialized` if called twice.
+    pub fn initialize(&self) -> Result<()> {
+        if self.initialized.swap(true, Ordering::SeqCst) {
+            return Err(Error::AlreadyInitialized);
+        }
+        self.cpp_module.initialize(self);
+
+        // Implement all commands for all of our implementations and subscribe to all variables
+        // of our requirements, dispatch everything to the Subscriber.
+        let mut registrar = RuntimeRegistrar {
+            runtime: self,
+            topology: self.log_topology.then(Topology::default),
+        };
+        wire_manifest(&self.manifest, &self.interfaces, &mut registrar)?;
+        if let Some(Topology {
+            provides,
+            subscribes,
+        }) = registrar.topology
+        {
+            log::info!(
+                "Module '{}' provides: {}; subscribes: {}",
+                self.module_id,
+                provides.join(", "),
+                subscribes.join(", ")
+            );
+        }
 
-                module
-                    .cpp_module
-                    .as_ref()
-                    .unwrap()
-                    .provide_command(&module, &meta);
+        // All registrations above are done, so we can tell EVerest that we are ready. The
+        // `on_ready` of the Subscriber is only called back once all modules did the same.
+        match &self.implementations_ready {
+            None => self.cpp_module.signal_ready(self),
+            Some(state) => {
+                let mut state = state.lock().unwrap();
+                state.initialized = true;
+                self.signal_ready_if_complete(&mut state);
             }
         }
+        Ok(())
+    }
+
+    /// Marks our implementation `implementation_id` as ready, for modules created with
+    /// [`RuntimeBuilder::ready_per_implementation`]. EVerest only knows whether a module as a
+    /// whole is ready, there is no partial readiness, so the module signals that it is ready once
+    /// `initialize` is done and all implementations are marked, whichever comes last. The global
+    /// ready signal, and with it `Subscriber::on_ready` of all modules, waits for that.
+    ///
+    /// Fails with `Error::UnknownImplementation` if we do not provide `implementation_id`.
+    /// Without `ready_per_implementation`, the module is ready after `initialize` and this does
+    /// nothing else.
+    pub fn signal_ready_for(&self, implementation_id: &str) -> Result<()> {
+        if !self.manifest.provides.contains_key(implementation_id) {
+            return Err(Error::UnknownImplementation {
+                implementation_id: implementation_id.to_string(),
+            });
+        }
+        if let Some(state) = &self.implementations_ready {
+            let mut state = state.lock().unwrap();
+            state.ready.insert(implementation_id.to_string());
+            self.signal_ready_if_complete(&mut state);
+        }
+        Ok(())
+    }
+
+    fn signal_ready_if_complete(&self, state: &mut ImplementationsReady) {
+        let complete = state.initialized
+            && self
+                .manifest
+                .provides
+                .keys()
+                .all(|id| state.ready.contains(id));
+        if complete && !state.signalled {
+            state.signalled = true;
+            self.cpp_module.signal_ready(self);
+        }
+    }
+
+    /// Whether EVerest signalled that all modules are ready, see `Subscriber::on_ready`, which
+    /// might still be running when this turns true.
+    pub fn is_ready(&self) -> bool {
+        self.publishes.is_ready()
+    }
+
+    /// Blocks until EVerest signalled that all modules are ready or `timeout` passed, returning
+    /// whether it is ready, i.e. for health checks and tests. Must not be called from a handler,
+    /// as the ready signal is delivered on the everest thread, too.
+    pub fn wait_until_ready(&self, timeout: Duration) -> bool {
+        self.publishes.wait_until_ready(timeout)
+    }
+
+    /// The id of this module in the config, as passed with `--module`.
+    pub fn module_id(&self) -> &str {
+        &self.module_id
+    }
+
+    /// The ids of the implementations this module provides according to its manifest, sorted.
+    pub fn implementation_ids(&self) -> Vec<&str> {
+        self.manifest.provides.keys().map(String::as_str).collect()
+    }
 
-        // Since users can choose to overwrite `on_ready`, we can call signal_ready right away.
-        // TODO(sirver): There were some doubts if this strategy is too inflexible, discuss design
-        // again.
-        module.cpp_module.as_ref().unwrap().signal_ready(&module);
-        module
+    /// The name of the interface that our implementation `implementation_id` implements.
+    pub fn interface_of(&self, implementation_id: &str) -> Option<&str> {
+        self.manifest
+            .provides
+            .get(implementation_id)
+            .map(|provides| provides.interface.as_str())
+    }
+
+    /// The ids of our implementations of the interface `interface_name`, sorted.
+    pub fn implementations_of_interface(&self, interface_name: &str) -> Vec<&str> {
+        self.manifest
+            .provides
+            .iter()
+            .filter(|(_, provides)| provides.interface == interface_name)
+            .map(|(implementation_id, _)| implementation_id.as_str())
+            .collect()
+    }
+
+    /// The manifest of this module, i.e. which implementations it provides and which requirements
+    /// it has.
+    pub fn manifest(&self) -> &schema::Manifest {
+        &self.manifest
+    }
+
+    /// Returns the definition of the command `name` of our implementation or requirement `id`,
+    /// with the names and types of its arguments and its result, i.e. for bridges that forward
+    /// commands generically.
+    pub fn command_schema(&self, id: &str, name: &str) -> Option<&schema::interface::Command> {
+        let interface = match self.manifest.provides.get(id) {
+            Some(provides) => &provides.interface,
+            None => &self.manifest.requires.get(id)?.interface,
+        };
+        self.interfaces.get(interface)?.cmds.get(name)
+    }
+
+    /// Returns what the interface of our implementation or requirement `id` declares about its
+    /// variable `name`, i.e. for bridges that render values generically.
+    pub fn variable_metadata(&self, id: &str, name: &str) -> Option<VariableMetadata<'_>> {
+        let interface = match self.manifest.provides.get(id) {
+            Some(provides) => &provides.interface,
+            None => &self.manifest.requires.get(id)?.interface,
+        };
+        let variable = self.interfaces.get(interface)?.vars.get(name)?;
+        Some(VariableMetadata {
+            unit: variable.unit.as_deref(),
+            description: variable.description.as_deref(),
+            type_: &variable.arg,
+        })
+    }
+
+    /// Returns the `version` of the interface `interface_name` as installed, if it declares one.
+    /// Only knows the interfaces that this module provides or requires with at least one
+    /// connection.
+    pub fn interface_version(&self, interface_name: &str) -> Option<String> {
+        self.interfaces.get(interface_name)?.version.clone()
+    }
+
+    /// Logs a warning if the major version of the installed interface `interface_name` differs
+    /// from `expected`, i.e. the `VERSION` of the generated code, and returns whether they match.
+    /// An installed interface without version matches nothing.
+    pub fn check_interface_version(&self, interface_name: &str, expected: &str) -> bool {
+        let major = |version: &str| version.split('.').next().unwrap_or_default().to_string();
+        match self.interface_version(interface_name) {
+            Some(version) if major(&version) == major(expected) => true,
+            version => {
+                log::warn!(
+                    "Interface '{interface_name}' has version {version:?}, but major version {} was expected.",
+                    major(expected)
+                );
+                false
+            }
+        }
+    }
+
+    /// Reports the implementations, requirements and config of this module as far as they are
+    /// known without connecting to EVerest, i.e. to lint a config in CI. Call this instead of
+    /// `initialize`: Creating the `Runtime` already loads the manifest and the interfaces and
+    /// fails if they do not parse, this additionally checks the config against the manifest.
+    pub fn validate_only(&self) -> Result<ValidationReport> {
+        validation::validate(self)
+    }
+
+    /// Returns the C++ side of the runtime, as an escape hatch for features of libframework that
+    /// are not wrapped yet. Unstable, this might change with any release.
+    ///
+    /// It can be passed to functions of a `cxx::bridge` of your own, which declares it as
+    /// `type Module = everestrs::RawModule;`. Its C++ side is declared in
+    /// `everestrs_sys/everestrs_sys.hpp` and `Module::everest()` returns the
+    /// `Everest::Everest` instance.
+    ///
+    /// # Safety
+    ///
+    /// The runtime assumes that it is the only one registering handlers, connecting and
+    /// disconnecting. Doing any of that through the returned module, or calling into it from C++
+    /// after the `Runtime` is dropped, is undefined behavior.
+    pub unsafe fn raw_module(&self) -> &RawModule {
+        &self.cpp_module
+    }
+
+    /// Returns the `config` block of this module from the config yaml, deserialized into `T`.
+    pub fn get_config<T: DeserializeOwned>(&self) -> Result<T> {
+        self.cpp_module.get_module_config().deserialize()
+    }
+
+    /// Returns the number of modules connected to our requirement `requirement_id`. Their
+    /// connection indices for `call_command` and `Subscriber::handle_variable` are
+    /// `0..connection_count`.
+    pub fn connection_count(&self, requirement_id: &str) -> usize {
+        self.cpp_module.get_connection_count(requirement_id)
+    }
+
+    /// Whether the config connects a module to our requirement `requirement_id` as connection
+    /// `index`, i.e. to skip optional requirements without providers before calling them.
+    /// Connections are fixed by the config, so this does not tell whether the module is running
+    /// yet, which `Subscriber::on_requirement_ready` reports.
+    pub fn is_connected(&self, requirement_id: &str, index: usize) -> bool {
+        index < self.connection_count(requirement_id)
+    }
+
+    /// Calls the command `name` on the module connected to our requirement `requirement_id` as
+    /// connection `index` with `args`, which must serialize into a JSON object mapping argument
+    /// names to values. `index` is 0 for requirements that allow only a single connection.
+    ///
+    /// This never panics: It fails with `Error::Serialization` if `args` cannot be serialized,
+    /// with `Error::InvalidArgument` if there is no connection `index`, with `Error::CommandFailed`
+    /// if the C++ side could not complete the call, with `Error::Command` if the callee returned an
+    /// error and with `Error::Deserialization` naming `R` if the result does not deserialize into
+    /// `R`.
+    ///
+    /// In debug builds, the result is first checked against the definition of the command in the
+    /// interface, failing with `Error::SchemaViolation` which names the offending field. This
+    /// points at the callee if it drifted from the interface.
+    pub fn call_command<T: Serialize, R: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+        args: &T,
+    ) -> Result<R> {
+        self.call_command_timed(requirement_id, index, name, args)
+            .map(|(result, _)| result)
+    }
+
+    /// Like [`Runtime::call_command`] for commands without arguments. These are sent as the empty
+    /// object `{}`, the shape libframework passes on to the callee for a command without
+    /// `arguments`. `&()` as `args` sends `null` instead, which libframework passes on as well and
+    /// only modules of this crate accept as no arguments.
+    pub fn call_command_noargs<R: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+    ) -> Result<R> {
+        self.call_command(requirement_id, index, name, &serde_json::Map::new())
+    }
+
+    /// Like [`Runtime::call_command`], but also returns how long the call took end to end, from
+    /// handing it to the C++ side until its result arrived, i.e. for spotting slow modules. The
+    /// same duration is passed to `Metrics::command_completed` for every call.
+    pub fn call_command_timed<T: Serialize, R: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+        args: &T,
+    ) -> Result<(R, Duration)> {
+        self.check_connection(requirement_id, index)?;
+        if let Some(metrics) = self.metrics.get() {
+            metrics.command_called(requirement_id, name);
+        }
+        let args = ffi::JsonBlob::from_vec(self.serialization.serialize(args)?);
+        let start = Instant::now();
+        let result = self
+            .cpp_module
+            .call_command(requirement_id, index, name, args);
+        let duration = start.elapsed();
+        if let Some(metrics) = self.metrics.get() {
+            metrics.command_completed(requirement_id, name, duration);
+        }
+        let result = result.map_err(|e| Error::CommandFailed(e.what().to_string()))?;
+        Ok((self.command_result(requirement_id, name, result)?, duration))
+    }
+
+    /// Like [`Runtime::call_command`], but repeats the call according to `policy` while it fails
+    /// with an error that may go away by itself, i.e. because the other module is still starting
+    /// up. These are `Error::CommandFailed`, which the C++ side returns if it could not deliver
+    /// the call or got no result, `Error::Timeout` of `RetryPolicy::attempt_timeout` and
+    /// `Error::Command` with `type_` `"Overloaded"`, which a callee with
+    /// `OverloadPolicy::Reject` returns. All other errors, i.e. errors of the callee, a missing
+    /// connection or a result that does not deserialize, are returned right away, as is the error
+    /// of the last attempt. No more attempts are made once the runtime shuts down.
+    ///
+    /// The callee sees every attempt, so only commands that are safe to repeat should be retried.
+    /// The calling thread sleeps between attempts.
+    pub fn call_command_retry<T: Serialize, R: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+        args: &T,
+        policy: RetryPolicy,
+    ) -> Result<R> {
+        let mut attempt = 1;
+        loop {
+            let result = match policy.attempt_timeout {
+                Some(timeout) => {
+                    self.call_command_timeout(requirement_id, index, name, args, timeout)
+                }
+                None => self.call_command(requirement_id, index, name, args),
+            };
+            match result {
+                Err(err)
+                    if attempt < policy.max_attempts
+                        && retry::is_retryable(&err)
+                        && !self.cancellation.is_cancelled() =>
+                {
+                    let backoff = policy.backoff(attempt);
+                    log::debug!(
+                        "Attempt {attempt} of '{name}' on '{requirement_id}' failed, retrying in {backoff:?}: {err}"
+                    );
+                    std::thread::sleep(backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Like [`Runtime::call_command`] with `serde_json::Value`s, for fully dynamic callers like
+    /// scripting bridges that do not know the types at compile time.
+    pub fn call_command_value(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+        args: &serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        self.call_command(requirement_id, index, name, args)
+    }
+
+    /// Like [`Runtime::call_command`], but returns `Error::Timeout` if the result did not arrive
+    /// within `timeout`, e.g. because the other module hangs. The call is not aborted, the C++
+    /// side keeps waiting for it in the background and drops the late result.
+    pub fn call_command_timeout<T: Serialize, R: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+        args: &T,
+        timeout: Duration,
+    ) -> Result<R> {
+        self.check_connection(requirement_id, index)?;
+        if let Some(metrics) = self.metrics.get() {
+            metrics.command_called(requirement_id, name);
+        }
+        let mut timed_out = false;
+        let args = ffi::JsonBlob::from_vec(self.serialization.serialize(args)?);
+        let start = Instant::now();
+        let result = self.cpp_module.call_command_with_timeout(
+            requirement_id,
+            index,
+            name,
+            args,
+            timeout.as_millis().try_into().unwrap_or(u64::MAX),
+            &mut timed_out,
+        );
+        if let Some(metrics) = self.metrics.get() {
+            metrics.command_completed(requirement_id, name, start.elapsed());
+        }
+        let result = result.map_err(|e| Error::CommandFailed(e.what().to_string()))?;
+        if timed_out {
+            return Err(Error::Timeout);
+        }
+        self.command_result(requirement_id, name, result)
+    }
+
+    fn command_result<R: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        name: &str,
+        result: ffi::CommandResult,
+    ) -> Result<R> {
+        if !result.is_error && cfg!(debug_assertions) {
+            let schema = self
+                .command_schema(requirement_id, name)
+                .and_then(|command| command.result.as_ref());
+            if let Some(schema) = schema {
+                let value: serde_json::Value = deserialize_slice(result.data.as_bytes())?;
+                schema.validate(&value).map_err(|e| {
+                    Error::SchemaViolation(format!("result of {requirement_id}/{name}: {e}"))
+                })?;
+            }
+        }
+        result.into_result()
+    }
+
+    fn check_connection(&self, requirement_id: &str, index: usize) -> Result<()> {
+        let count = self.connection_count(requirement_id);
+        if index >= count {
+            return Err(Error::InvalidArgument(
+                "index",
+                format!("no connection {index}, requirement '{requirement_id}' has {count}"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the value of the variable `name` that the module on connection `index` of our
+    /// requirement `requirement_id` published last, as retained by the broker, or `None` if it
+    /// never published one. This allows to look at a variable on demand, i.e. right after
+    /// startup, instead of waiting for the next publish in `Subscriber::handle_variable`.
+    ///
+    /// The first call for a variable subscribes to its retained value and blocks up to a second
+    /// for the broker to send it, later calls return the latest value right away. Fails with
+    /// `Error::InvalidArgument` if there is no connection `index` and with
+    /// `Error::Deserialization` naming `T` if the value does not deserialize into `T`.
+    pub fn get_latest_variable<T: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+    ) -> Result<Option<T>> {
+        self.check_connection(requirement_id, index)?;
+        let blob = self
+            .cpp_module
+            .get_latest_variable(requirement_id, index, name)
+            .map_err(|e| Error::InvalidArgument("requirement_id", e.what().to_string()))?;
+        if blob.data.is_empty() {
+            return Ok(None);
+        }
+        blob.deserialize().map(Some)
+    }
+
+    /// Publishes `value` as the variable `name` of our implementation `implementation_id` to all
+    /// modules that subscribed to it.
+    /// Values published before the module is ready are queued, see [`Runtime::publish_raw`].
+    /// Fails with `Error::Serialization` if `value` cannot be serialized, i.e. a map with
+    /// non-string keys, with `Error::UnknownImplementation` or `Error::UnknownVariable` if we do
+    /// not provide the variable and with `Error::PublishFailed` if libframework rejects the value,
+    /// i.e. because it does not match the interface.
+    ///
+    /// In debug builds, the value is first checked against the definition of the variable in the
+    /// interface, failing with `Error::SchemaViolation` which names the offending field. This
+    /// catches drift between a Rust type and the interface where the value is produced.
+    pub fn publish_variable<T: Serialize>(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        value: &T,
+    ) -> Result<()> {
+        let data = self.serialization.serialize(value)?;
+        self.publish_raw(implementation_id, name, &data)
+    }
+
+    /// Like [`Runtime::publish_variable`] with a `serde_json::Value`, for bridges that produce
+    /// their values dynamically.
+    pub fn publish_value(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        value: &serde_json::Value,
+    ) -> Result<()> {
+        self.publish_variable(implementation_id, name, value)
+    }
+
+    /// Like [`Runtime::publish_variable`], but skips the publish if `value` is the same as the
+    /// last one sent through this method for the variable, to save traffic for values that rarely
+    /// change. The first value always goes out. Returns whether it was published.
+    ///
+    /// This compares the serialized JSON bytewise, not the values, so i.e. a `HashMap` with the
+    /// same entries in a different order counts as changed.
+    pub fn publish_variable_if_changed<T: Serialize>(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        value: &T,
+    ) -> Result<bool> {
+        let data = self.serialization.serialize(value)?;
+        self.last_published
+            .publish_if_changed(implementation_id, name, data, |data| {
+                self.publish_raw(implementation_id, name, data)
+            })
+    }
+
+    /// Like [`Runtime::publish_variable`], but for callers that already have the serialized JSON
+    /// in `data`, which saves serializing it again.
+    /// With other than the default [`SerializationOptions`], `data` goes on the wire as it is
+    /// formatted.
+    ///
+    /// Until EVerest signalled that all modules are ready, i.e. before `Subscriber::on_ready`, the
+    /// value is queued instead and sent in order right before `on_ready` is called. Errors while
+    /// sending the queue are reported through `Subscriber::on_error`. At most
+    /// [`Runtime::MAX_PENDING_PUBLISHES`] values are queued, after that publishing fails with
+    /// `Error::PublishFailed`.
+    pub fn publish_raw(&self, implementation_id: &str, name: &str, data: &[u8]) -> Result<()> {
+        let var = self.provided_variable(implementation_id, name)?;
+        if cfg!(debug_assertions) {
+            let value: serde_json::Value = deserialize_slice(data)?;
+            var.validate(&value)
+                .map_err(|e| Error::SchemaViolation(format!("{implementation_id}/{name}: {e}")))?;
+        }
+        self.publishes.publish(
+            implementation_id,
+            name,
+            data,
+            |implementation_id, name, data| self.send_variable(implementation_id, name, data),
+        )
+    }
+
+    /// The maximum number of publishes that are queued until the module is ready, see
+    /// [`Runtime::publish_raw`].
+    pub const MAX_PENDING_PUBLISHES: usize = 1024;
+
+    /// Sends the publishes queued before the module was ready right away, instead of waiting for
+    /// `on_ready`. Only useful if the caller knows that the subscribers are already listening. All
+    /// queued values are sent, the first error is returned.
+    pub fn flush_pending_publishes(&self) -> Result<()> {
+        self.publishes.flush(|implementation_id, name, data| {
+            self.send_variable(implementation_id, name, data)
+        })
+    }
+
+    fn send_variable(&self, implementation_id: &str, name: &str, data: &[u8]) -> Result<()> {
+        // libframework re-encodes the value unless it is asked to keep its format, which only
+        // differs from the default one if other `SerializationOptions` were set.
+        let sent = if self.serialization == SerializationOptions::default() {
+            self.cpp_module
+                .publish_variable(implementation_id, name, data)
+        } else {
+            self.cpp_module
+                .publish_variable_formatted(implementation_id, name, data)
+        };
+        sent.map_err(|e| Error::PublishFailed(e.what().to_string()))?;
+        if let Some(metrics) = self.metrics.get() {
+            metrics.variable_published(implementation_id, name);
+        }
+        Ok(())
+    }
+
+    /// Returns the definition of the variable `name` of our implementation `implementation_id`.
+    fn provided_variable(
+        &self,
+        implementation_id: &str,
+        name: &str,
+    ) -> Result<&schema::interface::Variable> {
+        let provides = self
+            .manifest
+            .provides
+            .get(implementation_id)
+            .ok_or_else(|| Error::UnknownImplementation {
+                implementation_id: implementation_id.to_string(),
+            })?;
+        self.interfaces
+            .get(&provides.interface)
+            .and_then(|interface| interface.vars.get(name))
+            .ok_or_else(|| Error::UnknownVariable {
+                implementation_id: implementation_id.to_string(),
+                name: name.to_string(),
+            })
+    }
+
+    /// Returns a [`Progress`] that publishes on the variable `name` of our implementation
+    /// `implementation_id`, for a command handler to report how far it got. Fails with
+    /// `Error::UnknownImplementation` or `Error::UnknownVariable` if we do not provide the
+    /// variable.
+    pub fn progress(&self, implementation_id: &str, name: &str) -> Result<Progress<'_>> {
+        self.provided_variable(implementation_id, name)?;
+        Ok(Progress::new(self, implementation_id, name))
+    }
+
+    /// Returns a [`session::SessionEventPublisher`] for the variable `session_event` of our
+    /// implementation `implementation_id`. Fails like [`Runtime::progress`] if we do not provide
+    /// it.
+    #[cfg(feature = "session")]
+    pub fn session_events(
+        &self,
+        implementation_id: &str,
+    ) -> Result<session::SessionEventPublisher<'_>> {
+        self.provided_variable(implementation_id, "session_event")?;
+        Ok(session::SessionEventPublisher::new(self, implementation_id))
+    }
+
+    /// Subscribes to the variable `name` on all connections of our requirement `requirement_id`,
+    /// until the returned handle is dropped. The values go to the `handle_variable` of the
+    /// `Subscriber`, like those of the subscriptions made in `initialize`. This is for modules
+    /// that only watch a variable at times, which should return `false` from `subscribes_to` for
+    /// it, or they get every value twice, and for those that use
+    /// [`RuntimeBuilder::manual_subscriptions`]. Must be called after `initialize`, i.e. from
+    /// `Subscriber::on_ready`. [`SubscriptionHandle::detach`] keeps a subscription for good.
+    ///
+    /// Fails with `Error::UnknownRequirement` or `Error::UnknownVariable` if we do not require
+    /// the variable. A value that is already on its way may still arrive after the handle is
+    /// dropped.
+    pub fn subscribe(&self, requirement_id: &str, name: &str) -> Result<SubscriptionHandle> {
+        let requirement = self.manifest.requires.get(requirement_id).ok_or_else(|| {
+            Error::UnknownRequirement {
+                requirement_id: requirement_id.to_string(),
+            }
+        })?;
+        let connection_count = self.connection_count(requirement_id);
+        if connection_count == 0 {
+            // The interface is not loaded, but there is nothing to subscribe to either.
+            return Ok(SubscriptionHandle::new(self.this.clone(), Vec::new()));
+        }
+        if !self.interfaces[&requirement.interface]
+            .vars
+            .contains_key(name)
+        {
+            return Err(Error::UnknownVariable {
+                implementation_id: requirement_id.to_string(),
+                name: name.to_string(),
+            });
+        }
+        let subscription_ids = (0..connection_count)
+            .map(|index| {
+                let variable = ffi::VariableRef {
+                    requirement_id: requirement_id.to_string(),
+                    name: name.to_string(),
+                    index,
+                };
+                self.cpp_module.subscribe_variable(self, &variable)
+            })
+            .collect();
+        Ok(SubscriptionHandle::new(self.this.clone(), subscription_ids))
+    }
+
+    pub(crate) fn unsubscribe(&self, subscription_id: usize) {
+        self.cpp_module.unsubscribe_variable(subscription_id);
+    }
+
+    /// Starts a thread that publishes an incrementing counter, starting at 0, on the variable
+    /// `name` of our implementation `implementation_id` every `interval`, so that monitoring can
+    /// tell that the module is alive. The variable must hence be an integer. Replaces a heartbeat
+    /// already running on the variable and fails like [`Runtime::progress`] if we do not provide
+    /// it. Failed publishes are logged. The thread stops with [`Runtime::stop_heartbeat`] or
+    /// when we shut down.
+    pub fn start_heartbeat(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        interval: Duration,
+    ) -> Result<()> {
+        self.provided_variable(implementation_id, name)?;
+        let heartbeat =
+            heartbeat::Heartbeat::start(self.this.clone(), implementation_id, name, interval);
+        let previous = self
+            .heartbeats
+            .lock()
+            .unwrap()
+            .insert((implementation_id.to_string(), name.to_string()), heartbeat);
+        if let Some(previous) = previous {
+            previous.stop();
+        }
+        Ok(())
+    }
+
+    /// Stops the heartbeat on the variable `name` of `implementation_id` and waits for its thread
+    /// to finish. Returns whether one was running.
+    pub fn stop_heartbeat(&self, implementation_id: &str, name: &str) -> bool {
+        let heartbeat = self
+            .heartbeats
+            .lock()
+            .unwrap()
+            .remove(&(implementation_id.to_string(), name.to_string()));
+        match heartbeat {
+            Some(heartbeat) => {
+                heartbeat.stop();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn stop_heartbeats(&self) {
+        let heartbeats = std::mem::take(&mut *self.heartbeats.lock().unwrap());
+        for heartbeat in heartbeats.into_values() {
+            heartbeat.stop();
+        }
+    }
+
+    /// Publishes `data` on the telemetry topic `category` below the telemetry prefix of EVerest.
+    /// Telemetry is meant for metrics and monitoring and is separate from the variables of the
+    /// interfaces. This is a no-op, that does not even serialize `data`, if telemetry is not
+    /// enabled for this module in the config.
+    pub fn publish_telemetry<T: Serialize>(&self, category: &str, data: &T) -> Result<()> {
+        if !self.telemetry_enabled {
+            return Ok(());
+        }
+        self.cpp_module
+            .publish_telemetry(category, &serialize(data)?);
+        Ok(())
+    }
+
+    /// Publishes `payload` on `topic` below the external MQTT prefix of EVerest, for talking to
+    /// systems outside of EVerest without a separate MQTT client. Fails with
+    /// `Error::ExternalMqttDisabled` if the manifest does not set `enable_external_mqtt`.
+    pub fn mqtt_publish(&self, topic: &str, payload: &[u8]) -> Result<()> {
+        self.check_external_mqtt()?;
+        self.cpp_module
+            .publish_external_mqtt(topic, payload)
+            .map_err(|e| Error::PublishFailed(e.what().to_string()))
+    }
+
+    /// Calls `handler` with the payload of every message on `topic` below the external MQTT
+    /// prefix. The handler runs on the everest thread, so it must not block. Subscribing can
+    /// happen before or after `initialize`. Fails with `Error::ExternalMqttDisabled` if the
+    /// manifest does not set `enable_external_mqtt`.
+    pub fn mqtt_subscribe(
+        &self,
+        topic: &str,
+        handler: impl Fn(&[u8]) + Send + Sync + 'static,
+    ) -> Result<()> {
+        self.check_external_mqtt()?;
+        let handler_id = {
+            let mut handlers = self.external_mqtt_handlers.write().unwrap();
+            handlers.push(Arc::new(Box::new(handler)));
+            handlers.len() - 1
+        };
+        self.cpp_module
+            .subscribe_external_mqtt(self, topic, handler_id)
+            .map_err(|_| Error::Ffi {
+                op: "subscribe_external_mqtt",
+            })
+    }
+
+    fn check_external_mqtt(&self) -> Result<()> {
+        if !self.manifest.enable_external_mqtt {
+            return Err(Error::ExternalMqttDisabled);
+        }
+        Ok(())
+    }
+
+    /// Disconnects from the message broker and joins the main everest thread, after which no more
+    /// commands are dispatched to the `Subscriber`. Dropping the last reference to the `Runtime`
+    /// does the same, this just makes the shutdown explicit. Must not be called from a handler, as
+    /// those run on the everest thread.
+    ///
+    /// A `call_command` that a command handler issued from the everest thread while we shut down
+    /// fails with `Error::CommandFailed` once the C++ side gives up waiting for the result, as the
+    /// result can no longer arrive.
+    pub fn shutdown(&self) {
+        self.cancellation.cancel();
+        self.stop_heartbeats();
+        self.cpp_module.shutdown();
+    }
+
+    /// Returns a token that is cancelled when the runtime starts to shut down, i.e. because the
+    /// process received SIGTERM. Long running command handlers should check it and return early,
+    /// as the shutdown waits for running handlers.
+    ///
+    /// This token is shared by all handlers. The handlers of an `AsyncSubscriber` get a child of
+    /// it for each call, which is also cancelled once the caller gave up waiting for the result.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Blocks the calling thread until the process receives SIGINT or SIGTERM or
+    /// [`Runtime::shutdown`] is called from elsewhere, then shuts down. This is meant to be the
+    /// last call in `main`, after [`Runtime::initialize`]; it returns right away if the runtime is
+    /// not initialized.
+    pub fn run(&self) {
+        self.cpp_module.wait_for_shutdown_signal();
+        self.shutdown();
+    }
+
+    /// Parses the commandline and creates the module. This is a thin wrapper around
+    /// [`Runtime::try_new`] that exits on invalid commandline arguments and panics if the module
+    /// cannot be created.
+    pub fn from_commandline() -> Pin<Arc<Self>> {
+        let args: Args = argh::from_env();
+        Self::try_new(args).expect("Could not create the EVerest module.")
+    }
+
+    /// Creates the module described by `args`. See [`RuntimeBuilder`] for creating a module
+    /// without commandline arguments.
+    pub fn try_new(args: Args) -> Result<Pin<Arc<Self>>> {
+        RuntimeBuilder::from(args).build()
+    }
+
+    /// Like [`Runtime::from_commandline`], but also attaches `subscriber` with
+    /// [`Runtime::set_owned_subscriber`], for subscribers that do not hold on to the `Runtime`.
+    pub fn with_subscriber(subscriber: Arc<dyn Subscriber>) -> Pin<Arc<Self>> {
+        let runtime = Self::from_commandline();
+        runtime
+            .set_owned_subscriber(subscriber)
+            .expect("A new runtime has no subscriber.");
+        runtime
+    }
+
+    /// The whole `main` of a module, see [`module_main!`]: Installs the [`Logger`], creates the
+    /// runtime from the commandline, passes it to `new` to create the subscriber, which may hold
+    /// on to it, attaches that with [`Runtime::set_subscriber`], initializes and runs until the
+    /// module is asked to terminate. Exits on invalid commandline arguments and panics if the
+    /// module cannot be created or initialized.
+    pub fn run_module<S: Subscriber + 'static>(new: impl FnOnce(Pin<Arc<Runtime>>) -> S) {
+        // Only fails if there is another logger, which then keeps logging.
+        let _ = Self::init_logging();
+        let runtime = Self::from_commandline();
+        let module = Arc::new(new(runtime.clone()));
+        let subscriber: Weak<S> = Arc::downgrade(&module);
+        runtime
+            .set_subscriber(subscriber)
+            .expect("A new runtime has no subscriber.");
+        runtime
+            .initialize()
+            .expect("Could not initialize the EVerest module.");
+        runtime.run();
+    }
+}
+
+/// Defines the `main` of a module whose `Subscriber` is `$module`, which leaves writing the
+/// `Subscriber` implementation and the constructor. The constructor gets the
+/// `Pin<Arc<Runtime>>` and is `<$module>::new` unless given as second argument. See
+/// [`Runtime::run_module`] for what `main` does.
+///
+/// ```ignore
+/// struct Meter {
+///     runtime: Pin<Arc<Runtime>>,
+/// }
+///
+/// impl Meter {
+///     fn new(runtime: Pin<Arc<Runtime>>) -> Self {
+///         Self { runtime }
+///     }
+/// }
+///
+/// impl Subscriber for Meter {
+///     ..
+/// }
+///
+/// everestrs::module_main!(Meter);
+/// ```
+#[macro_export]
+macro_rules! module_main {
+    ($module:ty) => {
+        $crate::module_main!($module, <$module>::new);
+    };
+    ($module:ty, $new:expr) => {
+        fn main() {
+            $crate::Runtime::run_module::<$module>($new);
+        }
+    };
+}
+
+impl RuntimeApi for Runtime {
+    fn call_command<T: Serialize, R: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+        args: &T,
+    ) -> Result<R> {
+        Runtime::call_command(self, requirement_id, index, name, args)
+    }
+
+    fn call_command_noargs<R: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+    ) -> Result<R> {
+        Runtime::call_command_noargs(self, requirement_id, index, name)
+    }
+
+    fn publish_variable<T: Serialize>(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        value: &T,
+    ) -> Result<()> {
+        Runtime::publish_variable(self, implementation_id, name, value)
+    }
+}
+
+impl std::fmt::Debug for Runtime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Runtime")
+            .field("module_id", &self.module_id)
+            .field("has_subscriber", &self.subscriber().is_some())
+            .field("has_metrics", &self.metrics.get().is_some())
+            .field("initialized", &self.initialized.load(Ordering::SeqCst))
+            .field(
+                "provided_commands",
+                &self.provided_commands.load(Ordering::SeqCst),
+            )
+            .field(
+                "subscribed_variables",
+                &self.subscribed_variables.load(Ordering::SeqCst),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        // The everest thread calls into `self`, so it must be stopped before we go away. The
+        // heartbeats would stop by themselves, but not before their next tick.
+        self.cancellation.cancel();
+        self.stop_heartbeats();
+        self.cpp_module.shutdown();
+    }
+}
+
+/// Registers the commands and variables chosen by `wire_manifest` with libframework, dispatching
+/// them to the subscriber.
+struct RuntimeRegistrar<'a> {
+    runtime: &'a Runtime,
+    /// Collects the registrations for `RuntimeBuilder::log_topology`.
+    topology: Option<Topology>,
+}
+
+/// The commands and variables that `initialize` registered, as `<id>/<name>`.
+#[derive(Default)]
+struct Topology {
+    provides: Vec<String>,
+    subscribes: Vec<String>,
+}
+
+impl Registrar for RuntimeRegistrar<'_> {
+    fn connection_count(&self, requirement_id: &str) -> usize {
+        self.runtime.connection_count(requirement_id)
+    }
+
+    fn wants_command(&self, implementation_id: &str, name: &str) -> bool {
+        self.runtime
+            .subscriber_for(implementation_id)
+            .map_or(true, |subscriber| {
+                subscriber.provides_command(implementation_id, name)
+            })
+    }
+
+    fn wants_variable(&self, requirement_id: &str, name: &str) -> bool {
+        // With manual subscriptions, the subscriber subscribes itself with `subscribe`.
+        if self.runtime.manual_subscriptions {
+            return false;
+        }
+        self.runtime
+            .subscriber_for(requirement_id)
+            .map_or(true, |subscriber| {
+                subscriber.subscribes_to(requirement_id, name)
+            })
+    }
+
+    fn provide_command(&mut self, implementation_id: &str, name: &str) {
+        let command = ffi::CommandRef {
+            implementation_id: implementation_id.to_string(),
+            name: name.to_string(),
+        };
+        self.runtime
+            .cpp_module
+            .provide_command(self.runtime, &command);
+        self.runtime
+            .provided_commands
+            .fetch_add(1, Ordering::SeqCst);
+        if let Some(topology) = &mut self.topology {
+            topology
+                .provides
+                .push(format!("{implementation_id}/{name}"));
+        }
+    }
+
+    fn subscribe_variable(&mut self, requirement_id: &str, index: usize, name: &str) {
+        let variable = ffi::VariableRef {
+            requirement_id: requirement_id.to_string(),
+            name: name.to_string(),
+            index,
+        };
+        self.runtime
+            .cpp_module
+            .subscribe_variable(self.runtime, &variable);
+        self.runtime
+            .subscribed_variables
+            .fetch_add(1, Ordering::SeqCst);
+        // Listed once, no matter how many connections the requirement has.
+        if let (Some(topology), 0) = (&mut self.topology, index) {
+            topology.subscribes.push(format!("{requirement_id}/{name}"));
+        }
+    }
+}
+
+/// Configures and creates a [`Runtime`] programmatically, i.e. in processes that parse their own
+/// commandline or in test harnesses.
+#[derive(Debug, Default)]
+pub struct RuntimeBuilder {
+    module_id: Option<String>,
+    prefix: Option<PathBuf>,
+    conf: Option<PathBuf>,
+    conf_overlays: Vec<PathBuf>,
+    manual_subscriptions: bool,
+    log_topology: bool,
+    ready_per_implementation: bool,
+    max_concurrent_commands: Option<usize>,
+    overload_policy: OverloadPolicy,
+    serialization: SerializationOptions,
+}
+
+impl From<Args> for RuntimeBuilder {
+    fn from(args: Args) -> Self {
+        Self::new()
+            .module_id(&args.module)
+            .prefix(args.prefix)
+            .conf(args.conf)
+    }
+}
+
+impl RuntimeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The id of this module in the config yaml.
+    pub fn module_id(mut self, module_id: &str) -> Self {
+        self.module_id = Some(module_id.to_string());
+        self
+    }
+
+    /// The prefix of the EVerest installation.
+    pub fn prefix(mut self, prefix: impl Into<PathBuf>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// The config yaml that we are running.
+    pub fn conf(mut self, conf: impl Into<PathBuf>) -> Self {
+        self.conf = Some(conf.into());
+        self
+    }
+
+    /// Merges the yaml `overlay` onto the config, so that a deployment does not have to merge
+    /// its files before starting the module. Overlays are merged in the order they were added,
+    /// after the `user-config` that libframework merges onto the config, and the last one wins:
+    /// Mappings are merged key by key, a `null` value removes the key and all other values,
+    /// including lists, replace the previous one. The merged config is written to a temporary
+    /// file for libframework, which is removed once the module is created.
+    pub fn conf_overlay(mut self, overlay: impl Into<PathBuf>) -> Self {
+        self.conf_overlays.push(overlay.into());
+        self
+    }
+
+    /// Makes [`Runtime::initialize`] not subscribe to any variables, so that the subscriber has
+    /// full control over what it watches and subscribes with [`Runtime::subscribe`] itself, i.e.
+    /// in `Subscriber::on_ready` depending on the config. Values published before a subscription
+    /// are not received, as the broker does not retain them.
+    pub fn manual_subscriptions(mut self) -> Self {
+        self.manual_subscriptions = true;
+        self
+    }
+
+    /// Makes [`Runtime::initialize`] log which commands it provided and which variables it
+    /// subscribed to in one line, i.e. `provides: auth/validate, evse/enable; subscribes:
+    /// power_meter/power`, for diagnosing misconfigured modules in the field.
+    pub fn log_topology(mut self, log_topology: bool) -> Self {
+        self.log_topology = log_topology;
+        self
+    }
+
+    /// Makes [`Runtime::initialize`] not signal that the module is ready, that waits until every
+    /// implementation was marked as ready with [`Runtime::signal_ready_for`], i.e. for modules
+    /// where one implementation needs a slow hardware initialization.
+    pub fn ready_per_implementation(mut self) -> Self {
+        self.ready_per_implementation = true;
+        self
+    }
+
+    /// Runs at most `max` command handlers at the same time, i.e. for modules whose handlers share
+    /// a rate limited backend. Commands beyond that wait or fail, see
+    /// [`RuntimeBuilder::overload_policy`]. This covers `AsyncModule` as well, as its futures run
+    /// to completion inside the handler. libframework dispatches the commands of each
+    /// implementation on its own thread, one at a time, so this only has an effect in modules
+    /// with several implementations. With [`OverloadPolicy::Queue`], a handler must not call a
+    /// command of this very module, as it would wait for itself once the limit is reached.
+    ///
+    /// Waiting commands count against the timeout of their callers, who give up after 300
+    /// seconds. A command whose caller gave up still runs once it gets its turn, its result is
+    /// dropped. `max` must be at least 1.
+    pub fn max_concurrent_commands(mut self, max: usize) -> Self {
+        assert!(max > 0, "At least one command must be able to run.");
+        self.max_concurrent_commands = Some(max);
+        self
+    }
+
+    /// What happens to commands beyond [`RuntimeBuilder::max_concurrent_commands`]. They are
+    /// queued by default.
+    pub fn overload_policy(mut self, policy: OverloadPolicy) -> Self {
+        self.overload_policy = policy;
+        self
+    }
+
+    /// How published variables and command arguments are serialized, i.e. pretty printed for
+    /// reading the MQTT traffic. Compact by default, see [`SerializationOptions`] for the cost of
+    /// the other options and where libframework re-encodes the JSON anyway.
+    pub fn serialization(mut self, options: SerializationOptions) -> Self {
+        self.serialization = options;
+        self
+    }
+
+    /// Creates the module and loads its manifest and interfaces, but does not connect to EVerest
+    /// yet, see [`Runtime::initialize`].
+    ///
+    /// The C++ side holds pointers to the returned `Runtime` for its callbacks, so it is pinned on
+    /// the heap and must be kept alive for as long as the module should run.
+    pub fn build(self) -> Result<Pin<Arc<Runtime>>> {
+        let missing = |what: &str| Error::ModuleCreation(format!("the {what} is not set"));
+        let module_id = self.module_id.ok_or_else(|| missing("module id"))?;
+        let prefix = self.prefix.ok_or_else(|| missing("prefix"))?;
+        let mut conf = self.conf.ok_or_else(|| missing("config file"))?;
+
+        // libframework reads the config only while creating the module, so the merged one can
+        // go right after.
+        let merged_conf = if self.conf_overlays.is_empty() {
+            None
+        } else {
+            let merged_conf = conf::MergedConf::write(&module_id, &conf, &self.conf_overlays)?;
+            conf = merged_conf.path.clone();
+            Some(merged_conf)
+        };
+        let cpp_module = ffi::create_module(
+            &module_id,
+            &prefix.to_string_lossy(),
+            &conf.to_string_lossy(),
+        )
+        .map_err(|e| Error::ModuleCreation(e.what().to_string()))?;
+        drop(merged_conf);
+        if cpp_module.is_null() {
+            return Err(Error::Ffi {
+                op: "create_module",
+            });
+        }
+        let manifest: schema::Manifest = cpp_module.get_manifest().deserialize()?;
+        let mut interfaces = BTreeMap::new();
+        // libframework only loads the interfaces that are provided by some module of the config,
+        // so optional requirements without connections are left out.
+        let interface_names = manifest.provides.values().map(|p| &p.interface).chain(
+            manifest
+                .requires
+                .iter()
+                .filter(|(id, _)| cpp_module.get_connection_count(id) > 0)
+                .map(|(_, r)| &r.interface),
+        );
+        for name in interface_names {
+            if !interfaces.contains_key(name) {
+                // libframework returns `null` for interfaces it does not know.
+                let interface: schema::Interface = cpp_module
+                    .get_interface(name)
+                    .deserialize::<Option<_>>()?
+                    .ok_or_else(|| Error::UnknownInterface { name: name.clone() })?;
+                interfaces.insert(name.clone(), interface);
+            }
+        }
+        let telemetry_enabled = cpp_module.is_telemetry_enabled();
+        let runtime = Arc::new_cyclic(|this| Runtime {
+            cpp_module,
+            this: this.clone(),
+            module_id,
+            subscriber: RwLock::new(None),
+            subscribers_by_id: RwLock::new(HashMap::new()),
+            handlers: OnceLock::new(),
+            metrics: OnceLock::new(),
+            variable_tap: OnceLock::new(),
+            last_published: publishing::LastPublished::default(),
+            external_mqtt_handlers: RwLock::new(Vec::new()),
+            heartbeats: Mutex::new(HashMap::new()),
+            cancellation: CancellationToken::new(),
+            initialized: AtomicBool::new(false),
+            publishes: publishing::PublishQueue::new(Runtime::MAX_PENDING_PUBLISHES),
+            ready_requirements: Mutex::new(BTreeSet::new()),
+            telemetry_enabled,
+            provided_commands: AtomicUsize::new(0),
+            subscribed_variables: AtomicUsize::new(0),
+            manual_subscriptions: self.manual_subscriptions,
+            log_topology: self.log_topology,
+            implementations_ready: self
+                .ready_per_implementation
+                .then(|| Mutex::new(ImplementationsReady::default())),
+            command_limit: self
+                .max_concurrent_commands
+                .map(|max| concurrency::CommandLimit::new(max, self.overload_policy)),
+            serialization: self.serialization,
+            manifest,
+            interfaces,
+        });
+        // SAFETY: This is what `Arc::pin` does, the `Runtime` is never moved out of the `Arc`.
+        Ok(unsafe { Pin::new_unchecked(runtime) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the caller gets for the result of a handler. libframework forwards the encoded error
+    /// unchanged as the result.
+    fn round_trip(result: Result<serde_json::Value>) -> Result<serde_json::Value> {
+        ffi::CommandResult::from_handler(result).into_result()
+    }
+
+    #[test]
+    fn command_result_round_trips() {
+        let value = serde_json::json!({"current": 16});
+        assert_eq!(round_trip(Ok(value.clone())).unwrap(), value);
+    }
+
+    #[test]
+    fn mismatched_result_type_fails_to_deserialize() {
+        let result = ffi::CommandResult::from_handler(Ok(serde_json::json!({"current": 16})));
+        let err = result.into_result::<bool>().unwrap_err();
+        assert!(
+            matches!(&err, Error::Deserialization { context } if context.starts_with("`bool`: ")),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn unserializable_value_is_an_error() {
+        // JSON objects only have string keys.
+        let value = HashMap::from([((1, 2), 3)]);
+        let err = serialize(&value).unwrap_err();
+        assert!(matches!(err, Error::Serialization(_)), "{err:?}");
+        let err = testing::MockRuntime::new()
+            .publish_variable("main", "limits", &value)
+            .unwrap_err();
+        assert!(matches!(err, Error::Serialization(_)), "{err:?}");
+    }
+
+    #[test]
+    #[cfg(feature = "arbitrary-precision")]
+    fn decimal_number_round_trips_exactly() {
+        #[derive(Serialize, serde::Deserialize)]
+        struct Price {
+            total: serde_json::Number,
+        }
+        // As an `f64`, it would be rounded.
+        assert_ne!(12345678901234567.89_f64.to_string(), "12345678901234567.89");
+
+        // What `publish_variable` sends and what `handle_variable` gets.
+        let price = Price {
+            total: "12345678901234567.89".parse().unwrap(),
+        };
+        let data = serialize(&price).unwrap();
+        assert_eq!(data, br#"{"total":12345678901234567.89}"#);
+        let received: Price = ffi::JsonBlob::from_vec(data).deserialize().unwrap();
+        assert_eq!(received.total.to_string(), "12345678901234567.89");
+    }
+
+    #[test]
+    fn command_for_unknown_implementation_is_an_error() {
+        let manifest: schema::Manifest = serde_json::from_value(serde_json::json!({
+            "description": "A module",
+            "provides": {"main": {"interface": "evse_manager", "description": "The EVSE"}},
+            "metadata": {"license": "Apache-2.0", "authors": ["everest"]}
+        }))
+        .unwrap();
+        check_provided(&manifest, "main").unwrap();
+
+        let err = round_trip(check_provided(&manifest, "bogus").map(|()| serde_json::Value::Null))
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Command { type_, message } if type_ == "Internal" && message.contains("bogus")),
+            "{err:?}"
+        );
+    }
+
+    /// Panics for the command and variable `panic`.
+    struct Panicking;
+
+    impl Subscriber for Panicking {
+        fn handle_command(
+            &self,
+            _implementation_id: &str,
+            name: &str,
+            _parameters: HashMap<String, serde_json::Value>,
+        ) -> Result<serde_json::Value> {
+            match name {
+                "panic" => panic!("boom"),
+                _ => Ok(serde_json::Value::Bool(true)),
+            }
+        }
+
+        fn handle_variable(
+            &self,
+            _implementation_id: &str,
+            _index: usize,
+            name: &str,
+            _value: serde_json::Value,
+        ) -> Result<()> {
+            match name {
+                "panic" => panic!("boom"),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn panicking_handler_does_not_stop_later_calls() {
+        let subscriber = Panicking;
+        let call = |name| catch_panic(|| subscriber.handle_command("main", name, HashMap::new()));
+        let err = round_trip(call("panic")).unwrap_err();
+        assert!(
+            matches!(&err, Error::Command { type_, message } if type_ == "Internal" && message.contains("boom")),
+            "{err:?}"
+        );
+        assert_eq!(
+            round_trip(call("get")).unwrap(),
+            serde_json::Value::Bool(true)
+        );
+
+        let update = |name| catch_panic(|| subscriber.handle_variable_raw("meter", 0, name, b"1"));
+        assert!(
+            matches!(update("panic"), Err(Error::HandlerPanicked(message)) if message == "boom")
+        );
+        update("power").unwrap();
+    }
+
+    #[test]
+    fn failing_handler_reaches_caller() {
+        let err = round_trip(Err(Error::Command {
+            type_: "Busy".to_string(),
+            message: "connector busy".to_string(),
+        }))
+        .unwrap_err();
+        assert!(
+            matches!(&err, Error::Command { type_, message } if type_ == "Busy" && message == "connector busy"),
+            "{err:?}"
+        );
+        assert!(!retry::is_retryable(&err));
+    }
+
+    #[test]
+    fn overloaded_handler_is_retryable() {
+        let err = round_trip(Err(Error::Overloaded).context("setting the limits")).unwrap_err();
+        assert!(
+            matches!(&err, Error::Command { type_, message } if type_ == "Overloaded" && message.starts_with("setting the limits: ")),
+            "{err:?}"
+        );
+        assert!(retry::is_retryable(&err));
+    }
+
+    #[test]
+    fn other_handler_errors_are_internal() {
+        let err = round_trip(Err(Error::HandlerPanicked("boom".to_string()))).unwrap_err();
+        assert!(
+            matches!(&err, Error::Command { type_, .. } if type_ == "Internal"),
+            "{err:?}"
+        );
+        assert!(!retry::is_retryable(&err));
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    #[allow(dead_code)]
+    struct Limits {
+        current: f64,
+    }
+
+    #[test]
+    fn deserialization_error_names_type_and_position() {
+        let blob = ffi::JsonBlob::from_vec(br#"{"current": "sixteen"}"#.to_vec());
+        let err = blob.deserialize::<Limits>().unwrap_err();
+        let Error::Deserialization { context } = &err else {
+            panic!("{err:?}");
+        };
+        assert!(context.contains("Limits"), "{context}");
+        assert!(context.contains("line 1 column"), "{context}");
+    }
+
+    #[test]
+    fn invalid_argument_names_the_argument() {
+        let parameters = HashMap::from([("current".to_string(), serde_json::json!("sixteen"))]);
+        let err = required_arg::<f64>(&parameters, "current").unwrap_err();
+        assert!(
+            matches!(err, Error::InvalidArgument("current", _)),
+            "{err:?}"
+        );
+        let err = params_into::<Limits>(HashMap::new()).unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidArgument(type_, message) if type_.ends_with("Limits") && message.contains("`current`")),
+            "{err:?}"
+        );
     }
 }
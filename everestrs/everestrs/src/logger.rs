@@ -0,0 +1,37 @@
+use crate::ffi;
+
+/// A `log::Log` implementation that forwards all records to EVerest's logging, so that the output
+/// of Rust modules ends up in the same place and format as the one of the C++ modules.
+///
+/// The C++ logging does not depend on the module, so records logged before the `Runtime` exists
+/// are not lost either, they are just formatted with the default settings of the C++ side until
+/// the logging config has been loaded.
+pub struct Logger;
+
+impl log::Log for Logger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        // The filtering is done by the logging config of the C++ side.
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let level = match record.level() {
+            log::Level::Error => ffi::LogLevel::Error,
+            log::Level::Warn => ffi::LogLevel::Warning,
+            log::Level::Info => ffi::LogLevel::Info,
+            log::Level::Debug => ffi::LogLevel::Debug,
+            log::Level::Trace => ffi::LogLevel::Verbose,
+        };
+        ffi::log_message(level, &record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: Logger = Logger;
+
+pub(crate) fn init() -> std::result::Result<(), log::SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(log::LevelFilter::Trace);
+    Ok(())
+}
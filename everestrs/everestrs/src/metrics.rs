@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+/// A sink for counters about the traffic of a `Runtime`, installed with `Runtime::set_metrics`.
+/// All methods default to doing nothing, so an implementation only needs to override the counters
+/// it cares about, e.g. to increment `prometheus` counters labeled with the given names.
+///
+/// The methods are called on the threads delivering commands and variables, or the ones calling
+/// and publishing, so they should only increment counters and never block.
+pub trait Metrics: Send + Sync {
+    /// The command `name` of our implementation `implementation_id` was handled, successfully or
+    /// not.
+    fn command_handled(&self, _implementation_id: &str, _name: &str) {}
+
+    /// The variable `name` of our requirement `requirement_id` was received.
+    fn variable_received(&self, _requirement_id: &str, _name: &str) {}
+
+    /// The variable `name` of our implementation `implementation_id` was published.
+    fn variable_published(&self, _implementation_id: &str, _name: &str) {}
+
+    /// The command `name` was called on our requirement `requirement_id`.
+    fn command_called(&self, _requirement_id: &str, _name: &str) {}
+
+    /// A call of the command `name` on our requirement `requirement_id` returned after
+    /// `duration`, measured from handing it to the C++ side until its result arrived, the call
+    /// failed or, for `Runtime::call_command_timeout`, it timed out. Meant for latency histograms.
+    fn command_completed(&self, _requirement_id: &str, _name: &str, _duration: Duration) {}
+
+    /// The `Subscriber` failed to handle the command or variable `name` of `id`, which is an
+    /// implementation id for commands and a requirement id for variables.
+    fn handler_failed(&self, _id: &str, _name: &str) {}
+}
@@ -0,0 +1,46 @@
+use crate::{Result, Runtime};
+use serde::Serialize;
+
+/// Publishes the progress of a long running command on a variable of the same implementation,
+/// while the command is still executing, see [`Runtime::progress`]. EVerest commands only have a
+/// single result, so callers that want the intermediate steps subscribe to the variable.
+///
+/// ```ignore
+/// impl Subscriber for Updater {
+///     fn handle_command(
+///         &self,
+///         implementation_id: &str,
+///         name: &str,
+///         parameters: HashMap<String, serde_json::Value>,
+///     ) -> everestrs::Result<serde_json::Value> {
+///         // "update_firmware" reports its progress on the variable "update_progress".
+///         let progress = self.runtime.progress(implementation_id, "update_progress")?;
+///         for (done, chunk) in self.chunks(&parameters).enumerate() {
+///             self.flash(chunk)?;
+///             progress.publish(&(100 * (done + 1) / self.chunk_count()))?;
+///         }
+///         Ok(serde_json::Value::Bool(true))
+///     }
+/// }
+/// ```
+pub struct Progress<'a> {
+    runtime: &'a Runtime,
+    implementation_id: String,
+    name: String,
+}
+
+impl<'a> Progress<'a> {
+    pub(crate) fn new(runtime: &'a Runtime, implementation_id: &str, name: &str) -> Self {
+        Self {
+            runtime,
+            implementation_id: implementation_id.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    /// Publishes `value` on the variable, like [`Runtime::publish_variable`].
+    pub fn publish<T: Serialize>(&self, value: &T) -> Result<()> {
+        self.runtime
+            .publish_variable(&self.implementation_id, &self.name, value)
+    }
+}
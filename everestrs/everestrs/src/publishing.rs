@@ -0,0 +1,267 @@
+//! The state behind publishing variables that all threads of a module share, see [`PublishQueue`]
+//! and [`LastPublished`]. Sending is passed in, so that the `Runtime` sends through the C++ side.
+use crate::{Error, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// A variable published before the module was ready, see `Runtime::publish_raw`.
+struct PendingPublish {
+    implementation_id: String,
+    name: String,
+    data: Vec<u8>,
+}
+
+/// Holds back publishes until the module is ready and sends them in order once it is, see
+/// `Runtime::publish_raw`.
+pub(crate) struct PublishQueue {
+    /// The maximum number of queued publishes.
+    capacity: usize,
+    /// Set once EVerest signalled that all modules are ready. Until then, publishes are queued in
+    /// `pending`.
+    ready: AtomicBool,
+    /// Notified with `pending` held once `ready` is set, see `wait_until_ready`.
+    ready_cv: Condvar,
+    pending: Mutex<Vec<PendingPublish>>,
+}
+
+impl PublishQueue {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ready: AtomicBool::new(false),
+            ready_cv: Condvar::new(),
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    /// Sends `data` as the variable `name` of `implementation_id` with `send` once ready, until
+    /// then it is queued. Fails with `Error::PublishFailed` if the queue is full.
+    pub(crate) fn publish(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        data: &[u8],
+        send: impl FnOnce(&str, &str, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        if !self.is_ready() {
+            let mut pending = self.pending.lock().unwrap();
+            // Check again, `set_ready` might have sent the queue while we waited for the lock.
+            if !self.is_ready() {
+                if pending.len() >= self.capacity {
+                    return Err(Error::PublishFailed(format!(
+                        "{} publishes are already waiting for the module to be ready",
+                        pending.len()
+                    )));
+                }
+                pending.push(PendingPublish {
+                    implementation_id: implementation_id.to_string(),
+                    name: name.to_string(),
+                    data: data.to_vec(),
+                });
+                return Ok(());
+            }
+        }
+        send(implementation_id, name, data)
+    }
+
+    /// Sends the queued publishes with `send`, then sends all later ones right away. All queued
+    /// values are sent, the first error is returned.
+    pub(crate) fn set_ready(
+        &self,
+        send: impl FnMut(&str, &str, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        // Holding the lock until `ready` is set keeps concurrent publishes from overtaking the
+        // queued ones.
+        let mut pending = self.pending.lock().unwrap();
+        let result = send_all(&mut pending, send);
+        self.ready.store(true, Ordering::SeqCst);
+        self.ready_cv.notify_all();
+        result
+    }
+
+    /// Sends the queued publishes with `send` without becoming ready, see
+    /// `Runtime::flush_pending_publishes`.
+    pub(crate) fn flush(&self, send: impl FnMut(&str, &str, &[u8]) -> Result<()>) -> Result<()> {
+        let mut pending = self.pending.lock().unwrap();
+        send_all(&mut pending, send)
+    }
+
+    /// Blocks until `set_ready` was called or `timeout` passed, returning whether it is ready.
+    pub(crate) fn wait_until_ready(&self, timeout: Duration) -> bool {
+        let pending = self.pending.lock().unwrap();
+        let _pending = self
+            .ready_cv
+            .wait_timeout_while(pending, timeout, |_| !self.is_ready())
+            .unwrap();
+        self.is_ready()
+    }
+}
+
+fn send_all(
+    pending: &mut Vec<PendingPublish>,
+    mut send: impl FnMut(&str, &str, &[u8]) -> Result<()>,
+) -> Result<()> {
+    let mut result = Ok(());
+    for publish in pending.drain(..) {
+        let sent = send(&publish.implementation_id, &publish.name, &publish.data);
+        if result.is_ok() {
+            result = sent;
+        }
+    }
+    result
+}
+
+/// The last value published for each variable by `publish_variable_if_changed`, by
+/// implementation id and name, as serialized JSON.
+#[derive(Default)]
+pub(crate) struct LastPublished(Mutex<HashMap<(String, String), Vec<u8>>>);
+
+impl LastPublished {
+    /// Publishes `data` as the variable `name` of `implementation_id` with `publish`, unless it is
+    /// the same as the last value passed to this for the variable. Returns whether it was
+    /// published.
+    pub(crate) fn publish_if_changed(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        data: Vec<u8>,
+        publish: impl FnOnce(&[u8]) -> Result<()>,
+    ) -> Result<bool> {
+        let key = (implementation_id.to_string(), name.to_string());
+        // Held while publishing, so that concurrent calls cannot publish out of order.
+        let mut last_published = self.0.lock().unwrap();
+        if last_published.get(&key) == Some(&data) {
+            return Ok(false);
+        }
+        publish(&data)?;
+        last_published.insert(key, data);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+
+    const THREADS: usize = 8;
+    const PUBLISHES: usize = 100;
+
+    /// A sender that records what it sends, as (name, data).
+    fn recorder(
+        sent: &Mutex<Vec<(String, Vec<u8>)>>,
+    ) -> impl Fn(&str, &str, &[u8]) -> Result<()> + '_ {
+        move |_, name, data| {
+            sent.lock().unwrap().push((name.to_string(), data.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn publishes_from_many_threads_around_ready_keep_their_order() {
+        let queue = PublishQueue::new(THREADS * PUBLISHES);
+        let sent = Mutex::new(Vec::new());
+        // Every thread publishes half of its values before and half while the queue gets ready.
+        let halfway = Barrier::new(THREADS + 1);
+        std::thread::scope(|scope| {
+            for thread in 0..THREADS {
+                let (queue, sent, halfway) = (&queue, &sent, &halfway);
+                scope.spawn(move || {
+                    let name = thread.to_string();
+                    for publish in 0..PUBLISHES {
+                        if publish == PUBLISHES / 2 {
+                            halfway.wait();
+                        }
+                        let data = publish.to_string().into_bytes();
+                        queue.publish("main", &name, &data, recorder(sent)).unwrap();
+                    }
+                });
+            }
+            halfway.wait();
+            queue.set_ready(recorder(&sent)).unwrap();
+        });
+
+        let sent = sent.into_inner().unwrap();
+        assert_eq!(sent.len(), THREADS * PUBLISHES);
+        for thread in 0..THREADS {
+            let name = thread.to_string();
+            let values: Vec<usize> = sent
+                .iter()
+                .filter(|(n, _)| *n == name)
+                .map(|(_, data)| std::str::from_utf8(data).unwrap().parse().unwrap())
+                .collect();
+            assert_eq!(values, (0..PUBLISHES).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn publishes_beyond_the_capacity_fail() {
+        let queue = PublishQueue::new(2);
+        let sent = Mutex::new(Vec::new());
+        for data in [b"1", b"2"] {
+            queue.publish("main", "v", data, recorder(&sent)).unwrap();
+        }
+        let err = queue
+            .publish("main", "v", b"3", recorder(&sent))
+            .unwrap_err();
+        assert!(matches!(err, Error::PublishFailed(_)), "{err:?}");
+        assert!(sent.lock().unwrap().is_empty());
+
+        queue.set_ready(recorder(&sent)).unwrap();
+        queue.publish("main", "v", b"4", recorder(&sent)).unwrap();
+        let data: Vec<_> = sent
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|(_, d)| d)
+            .collect();
+        assert_eq!(data, [b"1", b"2", b"4"]);
+    }
+
+    #[test]
+    fn waiting_threads_wake_up_when_ready() {
+        let queue = PublishQueue::new(1);
+        assert!(!queue.wait_until_ready(Duration::from_millis(1)));
+        std::thread::scope(|scope| {
+            let waiting: Vec<_> = (0..THREADS)
+                .map(|_| scope.spawn(|| queue.wait_until_ready(Duration::from_secs(60))))
+                .collect();
+            queue.set_ready(|_, _, _| Ok(())).unwrap();
+            for waiting in waiting {
+                assert!(waiting.join().unwrap());
+            }
+        });
+    }
+
+    #[test]
+    fn the_same_value_from_many_threads_is_published_once() {
+        let last_published = LastPublished::default();
+        let sent = Mutex::new(Vec::new());
+        let published = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    scope.spawn(|| {
+                        last_published
+                            .publish_if_changed("main", "v", b"42".to_vec(), |data| {
+                                recorder(&sent)("main", "v", data)
+                            })
+                            .unwrap()
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .filter(|published| *published)
+                .count()
+        });
+        assert_eq!(published, 1);
+        assert_eq!(sent.into_inner().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,47 @@
+use crate::Error;
+use std::time::Duration;
+
+/// How [`Runtime::call_command_retry`](crate::Runtime::call_command_retry) retries a command
+/// that failed with a retryable error: It is called up to `max_attempts` times, at least once,
+/// and waits `initial_backoff` before the second attempt, twice as long before each further
+/// one, but never longer than `max_backoff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Gives up on a single attempt after this long with `Error::Timeout`, which is retried,
+    /// instead of waiting for the timeout of libframework.
+    pub attempt_timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            attempt_timeout: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns how long to wait after the failed attempt `attempt`, counting from 1.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Whether a call that failed with `err` might succeed if repeated, see
+/// `Runtime::call_command_retry`.
+pub(crate) fn is_retryable(err: &Error) -> bool {
+    match err {
+        Error::CommandFailed(_) | Error::Timeout => true,
+        Error::Command { type_, .. } => type_ == "Overloaded",
+        _ => false,
+    }
+}
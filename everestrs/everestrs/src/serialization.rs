@@ -0,0 +1,157 @@
+//! How the `Runtime` turns published values and command arguments into JSON, see
+//! [`SerializationOptions`].
+use crate::{Error, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+/// How the `Runtime` serializes the values of `publish_variable` and the arguments of
+/// `call_command`, see `RuntimeBuilder::serialization`. The default is compact JSON with the keys
+/// in the order the type writes them, i.e. the declaration order of struct fields.
+///
+/// Published variables keep this format on the wire, libframework only parses them to check them
+/// against the interface. Command arguments are re-encoded by libframework into the message of
+/// the call, compact and with sorted keys, so the options are lost for them on the way. Subscribers
+/// always get values re-encoded by libframework.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SerializationOptions {
+    /// Indents objects and arrays over several lines, i.e. for reading the MQTT traffic while
+    /// debugging. This costs: The indentation and newlines make payloads of nested values several
+    /// times larger, which every publish sends and every subscriber has to parse.
+    pub pretty: bool,
+    /// Sorts the keys of all objects, so that the same value is always written the same way, even
+    /// from a `HashMap`. This serializes values into a `serde_json::Value` first, which allocates
+    /// for every field. Objects built as `serde_json::Value` keep their keys in insertion order
+    /// only with the `preserve_order` feature of `serde_json`, otherwise they are sorted anyway.
+    pub sort_keys: bool,
+}
+
+impl SerializationOptions {
+    pub(crate) fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        let data = if self.sort_keys {
+            let value = sort_keys(serde_json::to_value(value).map_err(serialization_error)?);
+            self.write(&value)
+        } else {
+            self.write(value)
+        };
+        data.map_err(serialization_error)
+    }
+
+    fn write<T: Serialize>(&self, value: &T) -> serde_json::Result<Vec<u8>> {
+        if self.pretty {
+            serde_json::to_vec_pretty(value)
+        } else {
+            serde_json::to_vec(value)
+        }
+    }
+}
+
+fn serialization_error(e: serde_json::Error) -> Error {
+    Error::Serialization(e.to_string())
+}
+
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut fields: Vec<_> = map.into_iter().collect();
+            fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_keys(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sort_keys).collect()),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Serialize)]
+    struct Limits {
+        max_current: u32,
+        phases: Vec<Phase>,
+    }
+
+    #[derive(Serialize)]
+    struct Phase {
+        voltage: u32,
+        current: u32,
+    }
+
+    fn limits() -> Limits {
+        Limits {
+            max_current: 32,
+            phases: vec![Phase {
+                voltage: 230,
+                current: 16,
+            }],
+        }
+    }
+
+    fn serialize(options: SerializationOptions, value: &impl Serialize) -> String {
+        String::from_utf8(options.serialize(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn default_is_compact_in_declaration_order() {
+        assert_eq!(
+            serialize(SerializationOptions::default(), &limits()),
+            r#"{"max_current":32,"phases":[{"voltage":230,"current":16}]}"#
+        );
+    }
+
+    #[test]
+    fn pretty_indents() {
+        let options = SerializationOptions {
+            pretty: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            serialize(options, &limits()),
+            r#"{
+  "max_current": 32,
+  "phases": [
+    {
+      "voltage": 230,
+      "current": 16
+    }
+  ]
+}"#
+        );
+    }
+
+    #[test]
+    fn sort_keys_sorts_nested_objects() {
+        let options = SerializationOptions {
+            sort_keys: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            serialize(options, &limits()),
+            r#"{"max_current":32,"phases":[{"current":16,"voltage":230}]}"#
+        );
+        let map: HashMap<_, _> = ('a'..='z').map(|c| (c.to_string(), c as u32)).collect();
+        let expected: String = ('a'..='z')
+            .map(|c| format!(r#""{c}":{}"#, c as u32))
+            .collect::<Vec<_>>()
+            .join(",");
+        assert_eq!(serialize(options, &map), format!("{{{expected}}}"));
+    }
+
+    #[test]
+    fn unserializable_value_is_an_error() {
+        let options = SerializationOptions {
+            sort_keys: true,
+            ..Default::default()
+        };
+        let err = options
+            .serialize(&HashMap::from([((1, 2), 3)]))
+            .unwrap_err();
+        assert!(matches!(err, Error::Serialization(_)), "{err:?}");
+    }
+}
@@ -0,0 +1,118 @@
+//! Publishing the lifecycle events of charging sessions in one shape, see
+//! [`SessionEventPublisher`].
+use crate::{Result, Runtime};
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Publishes session events on a variable of one of our implementations, `session_event` unless
+/// set with [`SessionEventPublisher::with_variable`]. Every event is an object with the `uuid` of
+/// the session, a `timestamp` in RFC 3339, the name of the `event` and optionally its payload,
+/// under the name of the event in snake case:
+///
+/// ```json
+/// {
+///   "uuid": "3f1c..",
+///   "timestamp": "2023-09-01T12:00:00.000Z",
+///   "event": "TransactionFinished",
+///   "transaction_finished": { "meter_value": 1200 }
+/// }
+/// ```
+///
+/// The payloads are those of the interface of the variable, this only takes care of the common
+/// fields around them.
+pub struct SessionEventPublisher<'a> {
+    runtime: &'a Runtime,
+    implementation_id: String,
+    name: String,
+}
+
+impl<'a> SessionEventPublisher<'a> {
+    pub(crate) fn new(runtime: &'a Runtime, implementation_id: &str) -> Self {
+        Self {
+            runtime,
+            implementation_id: implementation_id.to_string(),
+            name: "session_event".to_string(),
+        }
+    }
+
+    /// Publishes on the variable `name` instead of `session_event`. Unlike
+    /// [`Runtime::session_events`], this does not check that the variable exists, publishing
+    /// fails then.
+    pub fn with_variable(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Publishes the `event` of the session `uuid` without a payload, i.e. `"SessionStarted"`.
+    pub fn publish(&self, uuid: &str, event: &str) -> Result<()> {
+        self.publish_event(uuid, event, None)
+    }
+
+    /// Publishes the `event` of the session `uuid` with its `payload`.
+    pub fn publish_with<T: Serialize>(&self, uuid: &str, event: &str, payload: &T) -> Result<()> {
+        self.publish_event(uuid, event, Some(crate::to_value(payload)?))
+    }
+
+    fn publish_event(
+        &self,
+        uuid: &str,
+        event: &str,
+        payload: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let mut value = serde_json::Map::new();
+        value.insert("uuid".to_string(), uuid.into());
+        value.insert("timestamp".to_string(), timestamp(SystemTime::now()).into());
+        value.insert("event".to_string(), event.into());
+        if let Some(payload) = payload {
+            value.insert(snake_case(event), payload);
+        }
+        self.runtime.publish_variable(
+            &self.implementation_id,
+            &self.name,
+            &serde_json::Value::Object(value),
+        )
+    }
+}
+
+/// `TransactionFinished` becomes `transaction_finished`.
+fn snake_case(event: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in event.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Formats `time` as RFC 3339 in UTC with milliseconds.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // The days since 1970-01-01 to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
@@ -0,0 +1,39 @@
+use crate::Runtime;
+use std::sync::Weak;
+
+/// A subscription to a variable of one of our requirements, made by [`Runtime::subscribe`]. The
+/// values go to the `handle_variable` of the `Subscriber` until this is dropped. It does not keep
+/// the `Runtime` alive, dropping it after the `Runtime` is a no-op.
+#[must_use = "dropping the handle unsubscribes again"]
+pub struct SubscriptionHandle {
+    runtime: Weak<Runtime>,
+    /// One subscription per connection of the requirement.
+    subscription_ids: Vec<usize>,
+}
+
+impl SubscriptionHandle {
+    pub(crate) fn new(runtime: Weak<Runtime>, subscription_ids: Vec<usize>) -> Self {
+        Self {
+            runtime,
+            subscription_ids,
+        }
+    }
+
+    /// Keeps the subscription for as long as the `Runtime` lives, i.e. for subscriptions made in
+    /// `Subscriber::on_ready` that cannot hold on to the handle.
+    pub fn detach(mut self) {
+        self.subscription_ids.clear();
+    }
+}
+
+impl Drop for SubscriptionHandle {
+    fn drop(&mut self) {
+        // The subscriptions went away with the `Runtime`.
+        let Some(runtime) = self.runtime.upgrade() else {
+            return;
+        };
+        for subscription_id in self.subscription_ids.drain(..) {
+            runtime.unsubscribe(subscription_id);
+        }
+    }
+}
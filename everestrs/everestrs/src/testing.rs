@@ -0,0 +1,253 @@
+//! Test doubles for unit testing module implementations without a running EVerest.
+use crate::publishing::LastPublished;
+use crate::{Error, Registrar, Result, RuntimeApi, Subscriber};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Serves the canned result of a command call, gets the arguments of the call.
+pub type CommandHandler = Box<dyn Fn(serde_json::Value) -> Result<serde_json::Value> + Send + Sync>;
+
+/// A variable published through [`MockRuntime::publish_variable`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublishedVariable {
+    pub implementation_id: String,
+    pub name: String,
+    pub value: serde_json::Value,
+}
+
+/// Stands in for the `Runtime` in tests: It has the same `publish_variable` and `call_command`
+/// methods and implements [`RuntimeApi`], so generated clients and publishers work on it, but
+/// records the published variables and answers command calls from the handlers registered with
+/// [`MockRuntime::with_command`]. It does not touch the C++ side.
+#[derive(Default)]
+pub struct MockRuntime {
+    commands: HashMap<(String, String), CommandHandler>,
+    published: Mutex<Vec<PublishedVariable>>,
+    /// The serialized values of `publish_variable_if_changed`.
+    last_published: LastPublished,
+}
+
+impl MockRuntime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers calls of the command `name` on the requirement `requirement_id` with `handler`,
+    /// regardless of the connection index.
+    pub fn with_command(
+        mut self,
+        requirement_id: &str,
+        name: &str,
+        handler: impl Fn(serde_json::Value) -> Result<serde_json::Value> + Send + Sync + 'static,
+    ) -> Self {
+        self.commands.insert(
+            (requirement_id.to_string(), name.to_string()),
+            Box::new(handler),
+        );
+        self
+    }
+
+    /// Records `value` as published variable `name` of `implementation_id`.
+    pub fn publish_variable<T: Serialize>(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        value: &T,
+    ) -> Result<()> {
+        let value = serde_json::to_value(value).map_err(|e| Error::Serialization(e.to_string()))?;
+        self.record(implementation_id, name, value);
+        Ok(())
+    }
+
+    /// Like the `Runtime`, records `value` only if its serialization differs from the last one
+    /// passed to this method for the variable. Returns whether it was recorded.
+    pub fn publish_variable_if_changed<T: Serialize>(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        value: &T,
+    ) -> Result<bool> {
+        let data = serde_json::to_vec(value).map_err(|e| Error::Serialization(e.to_string()))?;
+        self.last_published
+            .publish_if_changed(implementation_id, name, data, |data| {
+                self.publish_raw(implementation_id, name, data)
+            })
+    }
+
+    /// Records the JSON in `data` as published variable `name` of `implementation_id`. Fails if
+    /// `data` is not valid JSON.
+    pub fn publish_raw(&self, implementation_id: &str, name: &str, data: &[u8]) -> Result<()> {
+        let value =
+            serde_json::from_slice(data).map_err(|e| Error::PublishFailed(e.to_string()))?;
+        self.record(implementation_id, name, value);
+        Ok(())
+    }
+
+    fn record(&self, implementation_id: &str, name: &str, value: serde_json::Value) {
+        self.published.lock().unwrap().push(PublishedVariable {
+            implementation_id: implementation_id.to_string(),
+            name: name.to_string(),
+            value,
+        });
+    }
+
+    /// Calls the handler registered for `requirement_id` and `name`. Fails with
+    /// `Error::CommandFailed` if there is none.
+    pub fn call_command<T: Serialize, R: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        _index: usize,
+        name: &str,
+        args: &T,
+    ) -> Result<R> {
+        let handler = self
+            .commands
+            .get(&(requirement_id.to_string(), name.to_string()))
+            .ok_or_else(|| {
+                Error::CommandFailed(format!("no handler for '{requirement_id}->{name}'"))
+            })?;
+        let args = serde_json::to_value(args).map_err(|e| Error::Serialization(e.to_string()))?;
+        serde_json::from_value(handler(args)?).map_err(|e| Error::Deserialization {
+            context: e.to_string(),
+        })
+    }
+
+    /// All variables published so far, oldest first.
+    pub fn published_variables(&self) -> Vec<PublishedVariable> {
+        self.published.lock().unwrap().clone()
+    }
+}
+
+impl RuntimeApi for MockRuntime {
+    fn call_command<T: Serialize, R: DeserializeOwned>(
+        &self,
+        requirement_id: &str,
+        index: usize,
+        name: &str,
+        args: &T,
+    ) -> Result<R> {
+        MockRuntime::call_command(self, requirement_id, index, name, args)
+    }
+
+    fn publish_variable<T: Serialize>(
+        &self,
+        implementation_id: &str,
+        name: &str,
+        value: &T,
+    ) -> Result<()> {
+        MockRuntime::publish_variable(self, implementation_id, name, value)
+    }
+}
+
+/// Records what `wire_manifest` registers, for testing which commands a module provides and
+/// which variables it subscribes to for a given manifest and config without the C++ side.
+#[derive(Default)]
+pub struct RecordingRegistrar {
+    connections: HashMap<String, usize>,
+    subscriber: Option<Arc<dyn Subscriber>>,
+    /// The provided commands as implementation id and name, in the order they were provided.
+    pub provided_commands: Vec<(String, String)>,
+    /// The subscribed variables as requirement id, connection index and name, in the order they
+    /// were subscribed to.
+    pub subscribed_variables: Vec<(String, usize, String)>,
+}
+
+impl RecordingRegistrar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connects `count` modules to the requirement `requirement_id`. Requirements default to none.
+    pub fn with_connections(mut self, requirement_id: &str, count: usize) -> Self {
+        self.connections.insert(requirement_id.to_string(), count);
+        self
+    }
+
+    /// Asks `subscriber` which commands and variables it wants, like the `Runtime` does.
+    pub fn with_subscriber(mut self, subscriber: Arc<dyn Subscriber>) -> Self {
+        self.subscriber = Some(subscriber);
+        self
+    }
+}
+
+impl Registrar for RecordingRegistrar {
+    fn connection_count(&self, requirement_id: &str) -> usize {
+        self.connections.get(requirement_id).copied().unwrap_or(0)
+    }
+
+    fn wants_command(&self, implementation_id: &str, name: &str) -> bool {
+        self.subscriber
+            .as_ref()
+            .is_none_or(|subscriber| subscriber.provides_command(implementation_id, name))
+    }
+
+    fn wants_variable(&self, requirement_id: &str, name: &str) -> bool {
+        self.subscriber
+            .as_ref()
+            .is_none_or(|subscriber| subscriber.subscribes_to(requirement_id, name))
+    }
+
+    fn provide_command(&mut self, implementation_id: &str, name: &str) {
+        self.provided_commands
+            .push((implementation_id.to_string(), name.to_string()));
+    }
+
+    fn subscribe_variable(&mut self, requirement_id: &str, index: usize, name: &str) {
+        self.subscribed_variables
+            .push((requirement_id.to_string(), index, name.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_publishes_and_calls() {
+        const THREADS: usize = 8;
+        const CALLS: usize = 100;
+        let runtime = MockRuntime::new().with_command("meter", "get", Ok);
+        std::thread::scope(|scope| {
+            for thread in 0..THREADS {
+                let runtime = &runtime;
+                scope.spawn(move || {
+                    for call in 0..CALLS {
+                        runtime
+                            .publish_variable("main", "counter", &(thread, call))
+                            .unwrap();
+                        let result: (usize, usize) = runtime
+                            .call_command("meter", 0, "get", &(thread, call))
+                            .unwrap();
+                        assert_eq!(result, (thread, call));
+                    }
+                });
+            }
+        });
+        assert_eq!(runtime.published_variables().len(), THREADS * CALLS);
+    }
+
+    #[test]
+    fn works_through_the_runtime_api() {
+        // Like the generated clients and publishers, which are generic over the runtime.
+        fn reset_and_publish<R: RuntimeApi>(runtime: &R) -> Result<()> {
+            let reset: bool = runtime.call_command_noargs("meter", 0, "reset")?;
+            runtime.publish_variable("main", "reset", &reset)
+        }
+
+        let runtime = MockRuntime::new().with_command("meter", "reset", |args| {
+            assert_eq!(args, serde_json::json!({}));
+            Ok(true.into())
+        });
+        reset_and_publish(&runtime).unwrap();
+        assert_eq!(
+            runtime.published_variables(),
+            [PublishedVariable {
+                implementation_id: "main".to_string(),
+                name: "reset".to_string(),
+                value: true.into(),
+            }]
+        );
+    }
+}
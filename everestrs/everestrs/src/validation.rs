@@ -0,0 +1,119 @@
+use crate::schema::manifest::ConfigType;
+use crate::{Result, Runtime};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// What [`Runtime::validate_only`] found out about a module without connecting to EVerest.
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationReport {
+    /// Our implementations, by implementation id.
+    pub provides: BTreeMap<String, InterfaceReport>,
+    /// Our requirements, by requirement id.
+    pub requires: BTreeMap<String, RequirementReport>,
+    /// Mismatches between the config of the module and its manifest. Empty if all is well.
+    pub problems: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// The interface of an implementation.
+#[derive(Debug, Clone, Serialize)]
+pub struct InterfaceReport {
+    pub interface: String,
+    pub cmds: Vec<String>,
+    pub vars: Vec<String>,
+}
+
+/// The interface of a requirement and how many modules the config connects to it. Requirements
+/// without connections have no `cmds` and `vars`, as their interface is not loaded.
+#[derive(Debug, Clone, Serialize)]
+pub struct RequirementReport {
+    pub interface: String,
+    pub connections: usize,
+    pub cmds: Vec<String>,
+    pub vars: Vec<String>,
+}
+
+pub(crate) fn validate(runtime: &Runtime) -> Result<ValidationReport> {
+    let manifest = runtime.manifest();
+    let interface_report = |interface: &str| {
+        let (cmds, vars) = match runtime.interfaces.get(interface) {
+            Some(definition) => (
+                definition.cmds.keys().cloned().collect(),
+                definition.vars.keys().cloned().collect(),
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+        InterfaceReport {
+            interface: interface.to_string(),
+            cmds,
+            vars,
+        }
+    };
+
+    let provides = manifest
+        .provides
+        .iter()
+        .map(|(id, provides)| (id.clone(), interface_report(&provides.interface)))
+        .collect();
+    let requires = manifest
+        .requires
+        .iter()
+        .map(|(id, requires)| {
+            let InterfaceReport {
+                interface,
+                cmds,
+                vars,
+            } = interface_report(&requires.interface);
+            let report = RequirementReport {
+                interface,
+                connections: runtime.connection_count(id),
+                cmds,
+                vars,
+            };
+            (id.clone(), report)
+        })
+        .collect();
+
+    let config: BTreeMap<String, serde_json::Value> = runtime.get_config()?;
+    let mut problems = Vec::new();
+    for (key, entry) in &manifest.config {
+        let Some(value) = config.get(key) else {
+            if entry.default.is_none() {
+                problems.push(format!(
+                    "config entry '{key}' is not set and has no default"
+                ));
+            }
+            continue;
+        };
+        let matches = match entry.type_ {
+            ConfigType::Boolean => value.is_boolean(),
+            ConfigType::Integer => value.is_i64() || value.is_u64(),
+            ConfigType::Number => value.is_number(),
+            ConfigType::String => value.is_string(),
+        };
+        if !matches {
+            problems.push(format!(
+                "config entry '{key}' is {value}, but the manifest declares {:?}",
+                entry.type_
+            ));
+        }
+    }
+    for key in config.keys() {
+        if !manifest.config.contains_key(key) {
+            problems.push(format!(
+                "config entry '{key}' is not declared in the manifest"
+            ));
+        }
+    }
+
+    Ok(ValidationReport {
+        provides,
+        requires,
+        problems,
+    })
+}
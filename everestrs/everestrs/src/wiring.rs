@@ -0,0 +1,177 @@
+use crate::schema::{Interface, Manifest};
+use crate::{Error, Result};
+use std::collections::BTreeMap;
+
+/// Carries out the registrations that [`wire_manifest`] decides on. The `Runtime` registers with
+/// libframework, `testing::RecordingRegistrar` records them for tests.
+pub trait Registrar {
+    /// Returns the number of modules connected to the requirement `requirement_id` in the config.
+    fn connection_count(&self, requirement_id: &str) -> usize;
+
+    /// Whether the command `name` of `implementation_id` should be provided, see
+    /// `Subscriber::provides_command`.
+    fn wants_command(&self, _implementation_id: &str, _name: &str) -> bool {
+        true
+    }
+
+    /// Whether the variable `name` of `requirement_id` should be subscribed to, see
+    /// `Subscriber::subscribes_to`.
+    fn wants_variable(&self, _requirement_id: &str, _name: &str) -> bool {
+        true
+    }
+
+    fn provide_command(&mut self, implementation_id: &str, name: &str);
+
+    fn subscribe_variable(&mut self, requirement_id: &str, index: usize, name: &str);
+}
+
+/// Provides all commands of the implementations in `manifest` and subscribes to all variables of
+/// all connections of its requirements through `registrar`, skipping those it does not want.
+/// This is what `Runtime::initialize` does, without the C++ side. `interfaces` holds the
+/// definitions by name; interfaces of requirements without connections may be missing, all others
+/// fail with `Error::UnknownInterface`.
+pub fn wire_manifest(
+    manifest: &Manifest,
+    interfaces: &BTreeMap<String, Interface>,
+    registrar: &mut dyn Registrar,
+) -> Result<()> {
+    let interface = |name: &String| {
+        interfaces
+            .get(name)
+            .ok_or_else(|| Error::UnknownInterface { name: name.clone() })
+    };
+
+    for (implementation_id, implementation) in &manifest.provides {
+        for name in interface(&implementation.interface)?.cmds.keys() {
+            if registrar.wants_command(implementation_id, name) {
+                registrar.provide_command(implementation_id, name);
+            }
+        }
+    }
+
+    // The connections are tagged with their index, so that the Subscriber can tell them apart.
+    // libframework refuses configs whose number of connections is not within `min_connections`
+    // and `max_connections` of the manifest.
+    for (requirement_id, requirement) in &manifest.requires {
+        let connection_count = registrar.connection_count(requirement_id);
+        if connection_count == 0 {
+            // An optional requirement that is not connected, whose interface might not even be
+            // installed.
+            continue;
+        }
+        for name in interface(&requirement.interface)?.vars.keys() {
+            if !registrar.wants_variable(requirement_id, name) {
+                continue;
+            }
+            for index in 0..connection_count {
+                registrar.subscribe_variable(requirement_id, index, name);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RecordingRegistrar;
+
+    fn interfaces() -> BTreeMap<String, Interface> {
+        let cmd_only = serde_json::json!({
+            "description": "Only commands",
+            "cmds": {
+                "stop": {
+                    "description": "Stops",
+                    "result": {"type": "boolean", "description": "Whether it stopped"}
+                }
+            }
+        });
+        let var_only = serde_json::json!({
+            "description": "Only variables",
+            "vars": {
+                "power": {"type": "number", "description": "The power in W"}
+            }
+        });
+        BTreeMap::from([
+            (
+                "cmd_only".to_string(),
+                serde_json::from_value(cmd_only).unwrap(),
+            ),
+            (
+                "var_only".to_string(),
+                serde_json::from_value(var_only).unwrap(),
+            ),
+        ])
+    }
+
+    fn manifest() -> Manifest {
+        serde_json::from_value(serde_json::json!({
+            "description": "A module",
+            "provides": {
+                "commands": {"interface": "cmd_only", "description": "Commands"},
+                "variables": {"interface": "var_only", "description": "Variables"}
+            },
+            "requires": {
+                "commander": {"interface": "cmd_only"},
+                "meter": {"interface": "var_only"}
+            },
+            "metadata": {"license": "Apache-2.0", "authors": ["everest"]}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn unconnected_optional_requirement_is_skipped() {
+        let mut manifest = manifest();
+        let optional = serde_json::json!({
+            "interface": "not_installed",
+            "min_connections": 0,
+            "max_connections": 1
+        });
+        manifest.requires.insert(
+            "optional".to_string(),
+            serde_json::from_value(optional).unwrap(),
+        );
+        let mut registrar = RecordingRegistrar::new().with_connections("meter", 1);
+        wire_manifest(&manifest, &interfaces(), &mut registrar).unwrap();
+        assert_eq!(
+            registrar.subscribed_variables,
+            vec![("meter".to_string(), 0, "power".to_string())]
+        );
+
+        let mut registrar = RecordingRegistrar::new().with_connections("optional", 1);
+        let err = wire_manifest(&manifest, &interfaces(), &mut registrar).unwrap_err();
+        assert!(
+            matches!(&err, Error::UnknownInterface { name } if name == "not_installed"),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn interface_without_vars_is_only_provided() {
+        let mut registrar = RecordingRegistrar::new().with_connections("commander", 2);
+        wire_manifest(&manifest(), &interfaces(), &mut registrar).unwrap();
+        assert_eq!(
+            registrar.provided_commands,
+            vec![("commands".to_string(), "stop".to_string())]
+        );
+        assert!(registrar.subscribed_variables.is_empty());
+    }
+
+    #[test]
+    fn interface_without_cmds_is_only_subscribed() {
+        let mut registrar = RecordingRegistrar::new().with_connections("meter", 2);
+        wire_manifest(&manifest(), &interfaces(), &mut registrar).unwrap();
+        assert_eq!(
+            registrar.provided_commands,
+            vec![("commands".to_string(), "stop".to_string())]
+        );
+        assert_eq!(
+            registrar.subscribed_variables,
+            vec![
+                ("meter".to_string(), 0, "power".to_string()),
+                ("meter".to_string(), 1, "power".to_string()),
+            ]
+        );
+    }
+}
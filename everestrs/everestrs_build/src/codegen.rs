@@ -0,0 +1,1228 @@
+//! Generation of strongly typed Rust code from EVerest interface definitions.
+//!
+//! The `Runtime` only deals with JSON blobs and command names as strings. The functions in this
+//! module turn the interface definitions into Rust code on top of it, so that a typo in a command
+//! or argument name becomes a compile error.
+use crate::schema::interface::{Argument, Type, Variable};
+use crate::schema::manifest::ConfigType;
+use crate::schema::{DataTypes, Interface, Manifest, TypeRef};
+use crate::{Error, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Returns the Rust source of a module named after `interface_name` for the interface defined by
+/// `interface_json`.
+///
+/// For an interface `power_meter`, this emits `pub mod power_meter` containing
+/// - a `PowerMeterClient` with one method per command for calling them on a requirement, or on one
+///   of its connections, and one `<Command>Args` struct per command that takes arguments, whose
+///   `validate` checks them against the `minimum`, `maximum` and `multipleOf` of the interface,
+/// - a `PowerMeterPublisher` with one method per variable for publishing them on one of our
+///   implementations. Both work on any `everestrs::RuntimeApi`, the `Runtime` by default, so that
+///   they can be tested against a `testing::MockRuntime`,
+/// - a `PowerMeterImpl` trait with one typed method per command for providing the interface and
+///   a `dispatch_command` function that extracts and validates the arguments and calls the
+///   matching method,
+/// - a `PowerMeterSubscriber` trait with one typed method per variable for requiring the
+///   interface and a `dispatch_variable` function that deserializes the raw value straight into
+///   its type and calls the matching method,
+/// - an enum for every string that is restricted to an `enum` of values, named after the variable
+///   (e.g. `Status`), the command argument (e.g. `StartTransactionMode`) or the command result
+///   (e.g. `StopResult`) it belongs to. Deserializing any other value fails.
+///
+/// All generated structs and enums convert from a `serde_json::Value` with `TryFrom` and into one
+/// with `From`, like with `everestrs::from_value` and `everestrs::to_value`.
+///
+/// Methods and arguments are named in snake case, i.e. `connectorId` becomes `connector_id`, and
+/// names that are no valid identifiers are sanitized, i.e. `type` becomes `type_`, `set-limit`
+/// becomes `set_limit` and `2nd_value` becomes `_2nd_value`. The names on the wire stay those of
+/// the interface.
+pub fn emit_interface(interface_name: &str, interface_json: &str) -> Result<String> {
+    let interface = parse_interface(interface_json)?;
+    let mut out = String::new();
+    let files = BTreeMap::new();
+    write_interface(
+        &mut out,
+        interface_name,
+        &interface,
+        Types::of_interfaces(&files),
+    )
+    .expect("Writing to a String cannot fail.");
+    Ok(out)
+}
+
+/// Returns the Rust source for the module described by `manifest_json`: The modules for all
+/// interfaces it provides or requires (see [`emit_interface`]), a `ModuleConfig` struct with one
+/// typed field per config entry, a `Publishers` struct with the publisher of each provided
+/// implementation and a `Module` struct with one field per provided implementation that
+/// implements `Subscriber` by dispatching to them.
+/// `interface_json` must return the definition of the interface with the given name.
+pub fn emit_module(
+    manifest_json: &str,
+    interface_json: impl Fn(&str) -> Result<String>,
+) -> Result<String> {
+    let manifest: Manifest =
+        serde_json::from_str(manifest_json).map_err(|e| Error::Deserialization {
+            context: e.to_string(),
+        })?;
+    let mut interfaces = BTreeMap::new();
+    let names = manifest
+        .provides
+        .values()
+        .map(|p| &p.interface)
+        .chain(manifest.requires.values().map(|r| &r.interface));
+    for name in names {
+        if !interfaces.contains_key(name) {
+            interfaces.insert(name.clone(), parse_interface(&interface_json(name)?)?);
+        }
+    }
+
+    let mut out = String::new();
+    write_module(&mut out, &manifest, &interfaces).expect("Writing to a String cannot fail.");
+    Ok(out)
+}
+
+fn parse_interface(interface_json: &str) -> Result<Interface> {
+    serde_json::from_str(interface_json).map_err(|e| Error::Deserialization {
+        context: e.to_string(),
+    })
+}
+
+fn write_module(
+    out: &mut String,
+    manifest: &Manifest,
+    interfaces: &BTreeMap<String, Interface>,
+) -> std::fmt::Result {
+    for (name, interface) in interfaces {
+        write_interface(out, name, interface, Types::of_interfaces(&BTreeMap::new()))?;
+        writeln!(out)?;
+    }
+
+    write_module_config(out, manifest)?;
+    write_publishers(out, manifest)?;
+
+    writeln!(
+        out,
+        "/// The implementations of this module, dispatches commands to the matching implementation."
+    )?;
+    writeln!(out, "pub struct Module {{")?;
+    for (implementation_id, provides) in &manifest.provides {
+        write_doc(out, "    ", &provides.description)?;
+        writeln!(
+            out,
+            "    pub {}: Box<dyn {}::{}Impl>,",
+            module_name(implementation_id),
+            module_name(&provides.interface),
+            camel_case(&provides.interface)
+        )?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "impl ::everestrs::Subscriber for Module {{")?;
+    writeln!(out, "    fn handle_command(")?;
+    writeln!(out, "        &self,")?;
+    writeln!(out, "        implementation_id: &str,")?;
+    writeln!(out, "        name: &str,")?;
+    writeln!(
+        out,
+        "        parameters: ::std::collections::HashMap<String, ::everestrs::serde_json::Value>,"
+    )?;
+    writeln!(
+        out,
+        "    ) -> ::everestrs::Result<::everestrs::serde_json::Value> {{"
+    )?;
+    writeln!(out, "        match implementation_id {{")?;
+    for (implementation_id, provides) in &manifest.provides {
+        writeln!(
+            out,
+            "            \"{implementation_id}\" => {}::dispatch_command(&*self.{}, implementation_id, name, parameters),",
+            module_name(&provides.interface),
+            module_name(implementation_id)
+        )?;
+    }
+    writeln!(
+        out,
+        "            _ => Err(::everestrs::Error::UnhandledCommand {{ implementation_id: implementation_id.to_string(), name: name.to_string() }}),"
+    )?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn write_module_config(out: &mut String, manifest: &Manifest) -> std::fmt::Result {
+    writeln!(
+        out,
+        "/// The config of this module, read with `ModuleConfig::from_runtime`."
+    )?;
+    writeln!(
+        out,
+        "#[derive(Debug, Clone, ::everestrs::serde::Deserialize)]"
+    )?;
+    writeln!(out, "#[serde(crate = \"::everestrs::serde\")]")?;
+    writeln!(out, "pub struct ModuleConfig {{")?;
+    for (key, entry) in &manifest.config {
+        write_doc(out, "    ", &entry.description)?;
+        let field = field_name(key);
+        if field != *key {
+            writeln!(out, "    #[serde(rename = \"{key}\")]")?;
+        }
+        if entry
+            .default
+            .as_ref()
+            .and_then(|d| config_literal(entry.type_, d))
+            .is_some()
+        {
+            writeln!(
+                out,
+                "    #[serde(default = \"ModuleConfig::{}\")]",
+                default_fn_name(key)
+            )?;
+        }
+        writeln!(out, "    pub {field}: {},", config_type(entry.type_))?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    writeln!(out, "impl ModuleConfig {{")?;
+    writeln!(
+        out,
+        "    /// Reads the config of this module from `runtime`, using the defaults of the manifest for missing entries. Fails with `Error::MissingConfig` for entries without a default that are not set."
+    )?;
+    writeln!(
+        out,
+        "    pub fn from_runtime(runtime: &::everestrs::Runtime) -> ::everestrs::Result<Self> {{"
+    )?;
+    writeln!(
+        out,
+        "        let config: ::everestrs::serde_json::Map<String, ::everestrs::serde_json::Value> = runtime.get_config()?;"
+    )?;
+    for (key, entry) in &manifest.config {
+        if entry
+            .default
+            .as_ref()
+            .and_then(|d| config_literal(entry.type_, d))
+            .is_none()
+        {
+            writeln!(out, "        if !config.contains_key(\"{key}\") {{")?;
+            writeln!(
+                out,
+                "            return Err(::everestrs::Error::MissingConfig {{ key: \"{key}\".to_string() }});"
+            )?;
+            writeln!(out, "        }}")?;
+        }
+    }
+    writeln!(
+        out,
+        "        ::everestrs::serde_json::from_value(::everestrs::serde_json::Value::Object(config))"
+    )?;
+    writeln!(
+        out,
+        "            .map_err(|e| ::everestrs::Error::Deserialization {{ context: format!(\"`ModuleConfig`: {{e}}\") }})"
+    )?;
+    writeln!(out, "    }}")?;
+    for (key, entry) in &manifest.config {
+        let Some(literal) = entry
+            .default
+            .as_ref()
+            .and_then(|d| config_literal(entry.type_, d))
+        else {
+            continue;
+        };
+        writeln!(out)?;
+        writeln!(
+            out,
+            "    fn {}() -> {} {{",
+            default_fn_name(key),
+            config_type(entry.type_)
+        )?;
+        writeln!(out, "        {literal}")?;
+        writeln!(out, "    }}")?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// The name of the function returning the default of the config entry `key`, i.e.
+/// `default_max_current` for `max-current`.
+fn default_fn_name(key: &str) -> String {
+    // `_2nd_value` would give a name with two underscores in a row, which is not snake case.
+    format!("default_{}", field_name(key).trim_start_matches('_'))
+}
+
+fn config_type(type_: ConfigType) -> &'static str {
+    match type_ {
+        ConfigType::Boolean => "bool",
+        ConfigType::Integer => "i64",
+        ConfigType::Number => "f64",
+        ConfigType::String => "String",
+    }
+}
+
+/// Returns the Rust expression for the `default` of a config entry of type `type_`, or `None` if
+/// the default does not have that type.
+fn config_literal(type_: ConfigType, default: &serde_json::Value) -> Option<String> {
+    match type_ {
+        ConfigType::Boolean => default.as_bool().map(|b| b.to_string()),
+        ConfigType::Integer => default.as_i64().map(|i| format!("{i}")),
+        ConfigType::Number => default.as_f64().map(|f| format!("{f:?}")),
+        ConfigType::String => default.as_str().map(|s| format!("{s:?}.to_string()")),
+    }
+}
+
+fn write_publishers(out: &mut String, manifest: &Manifest) -> std::fmt::Result {
+    // Without implementations, there is nothing to publish.
+    if manifest.provides.is_empty() {
+        return Ok(());
+    }
+    writeln!(
+        out,
+        "/// The publishers for the variables of all implementations of this module."
+    )?;
+    writeln!(
+        out,
+        "pub struct Publishers<'a, R: ::everestrs::RuntimeApi = ::everestrs::Runtime> {{"
+    )?;
+    for (implementation_id, provides) in &manifest.provides {
+        write_doc(out, "    ", &provides.description)?;
+        writeln!(
+            out,
+            "    pub {}: {}::{}Publisher<'a, R>,",
+            module_name(implementation_id),
+            module_name(&provides.interface),
+            camel_case(&provides.interface)
+        )?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "impl<'a, R: ::everestrs::RuntimeApi> Publishers<'a, R> {{"
+    )?;
+    writeln!(out, "    pub fn new(runtime: &'a R) -> Self {{")?;
+    writeln!(out, "        Self {{")?;
+    for (implementation_id, provides) in &manifest.provides {
+        writeln!(
+            out,
+            "            {}: {}::{}Publisher::new(runtime, \"{implementation_id}\"),",
+            module_name(implementation_id),
+            module_name(&provides.interface),
+            camel_case(&provides.interface)
+        )?;
+    }
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Where `$ref`s to shared types resolve to: The type files by name, and the path from the
+/// generated module to the `types` module that [`write_types`] emits them in.
+#[derive(Clone, Copy)]
+pub(crate) struct Types<'a> {
+    files: &'a BTreeMap<String, DataTypes>,
+    path: &'static str,
+}
+
+impl<'a> Types<'a> {
+    /// For the modules of interfaces, which are siblings of the `types` module.
+    pub(crate) fn of_interfaces(files: &'a BTreeMap<String, DataTypes>) -> Self {
+        Self {
+            files,
+            path: "super::types::",
+        }
+    }
+
+    /// Returns the path of the Rust type for `reference`, or `None` if it does not resolve, in
+    /// which case the value is typed as if it had no `$ref`.
+    fn path_of(&self, reference: &str) -> Option<String> {
+        let type_ref = TypeRef::parse(reference)?;
+        type_ref.resolve(self.files)?;
+        Some(format!(
+            "{}{}::{}",
+            self.path,
+            module_name(type_ref.file),
+            camel_case(type_ref.name)
+        ))
+    }
+}
+
+/// Writes the `types` module with one module per type file in `files`, each holding a Rust type
+/// per type definition. Objects become structs, whose properties that are not `required` become
+/// `Option`s, strings restricted to an `enum` of values become enums and everything else a type
+/// alias. Every type is emitted once, no matter how many interfaces refer to it.
+pub(crate) fn write_types(
+    out: &mut String,
+    files: &BTreeMap<String, DataTypes>,
+) -> std::fmt::Result {
+    let types = Types {
+        files,
+        path: "super::",
+    };
+    writeln!(
+        out,
+        "/// The shared types of the interfaces, which they refer to with `$ref`."
+    )?;
+    writeln!(out, "pub mod types {{")?;
+    for (file, data_types) in files {
+        write_doc(out, "    ", &data_types.description)?;
+        writeln!(out, "    pub mod {} {{", module_name(file))?;
+
+        let mut enums = BTreeMap::new();
+        for (name, var) in &data_types.types {
+            match &var.arg {
+                Argument::Single(Type::Object(options)) => {
+                    for (property, definition) in &options.properties {
+                        let enum_name = format!("{}{}", camel_case(name), camel_case(property));
+                        collect_enums(definition, &enum_name, &mut enums);
+                    }
+                }
+                Argument::Single(Type::String(_)) => {
+                    collect_enums(var, &camel_case(name), &mut enums)
+                }
+                _ => collect_enums(var, &format!("{}Item", camel_case(name)), &mut enums),
+            }
+        }
+        for (enum_name, values) in &enums {
+            write_enum(out, "        ", enum_name, values)?;
+        }
+
+        for (name, var) in &data_types.types {
+            let type_name = camel_case(name);
+            if enums.contains_key(&type_name) {
+                // The enum above already is the type.
+                continue;
+            }
+            if let Some(description) = &var.description {
+                write_doc(out, "        ", description)?;
+            }
+            let options = match &var.arg {
+                Argument::Single(Type::Object(options)) if options.object_reference.is_none() => {
+                    options
+                }
+                _ => {
+                    writeln!(
+                        out,
+                        "        pub type {type_name} = {};",
+                        rust_type(var, &format!("{type_name}Item"), types)
+                    )?;
+                    writeln!(out)?;
+                    continue;
+                }
+            };
+            writeln!(
+                out,
+                "        #[derive(Debug, Clone, ::everestrs::serde::Serialize, ::everestrs::serde::Deserialize)]"
+            )?;
+            writeln!(out, "        #[serde(crate = \"::everestrs::serde\")]")?;
+            writeln!(out, "        pub struct {type_name} {{")?;
+            for (property, definition) in &options.properties {
+                if let Some(description) = &definition.description {
+                    write_doc(out, "            ", description)?;
+                }
+                let field = field_name(property);
+                if field != *property {
+                    writeln!(out, "            #[serde(rename = \"{property}\")]")?;
+                }
+                let enum_name = format!("{type_name}{}", camel_case(property));
+                let mut field_type = rust_type(definition, &enum_name, types);
+                if !options.required.contains(property) {
+                    if !is_nullable(definition) {
+                        field_type = format!("Option<{field_type}>");
+                    }
+                    writeln!(
+                        out,
+                        "            #[serde(default, skip_serializing_if = \"Option::is_none\")]"
+                    )?;
+                }
+                writeln!(out, "            pub {field}: {field_type},")?;
+            }
+            writeln!(out, "        }}")?;
+            writeln!(out)?;
+            write_value_conversions(out, "        ", &type_name)?;
+        }
+        writeln!(out, "    }}")?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+pub(crate) fn write_interface(
+    out: &mut String,
+    interface_name: &str,
+    interface: &Interface,
+    types: Types,
+) -> std::fmt::Result {
+    let client_name = format!("{}Client", camel_case(interface_name));
+    let publisher_name = format!("{}Publisher", camel_case(interface_name));
+    let impl_name = format!("{}Impl", camel_case(interface_name));
+    let subscriber_name = format!("{}Subscriber", camel_case(interface_name));
+
+    write_doc(out, "", &interface.description)?;
+    writeln!(out, "pub mod {} {{", module_name(interface_name))?;
+    writeln!(
+        out,
+        "    /// The version of `{interface_name}` this code was generated from, see `Runtime::check_interface_version`."
+    )?;
+    writeln!(
+        out,
+        "    pub const VERSION: Option<&str> = {:?};",
+        interface.version
+    )?;
+    writeln!(out)?;
+
+    let mut enums = BTreeMap::new();
+    for (name, cmd) in &interface.cmds {
+        for (arg_name, arg) in &cmd.arguments {
+            collect_enums(arg, &argument_type_name(name, arg_name), &mut enums);
+        }
+        if let Some(result) = &cmd.result {
+            collect_enums(result, &result_type_name(name), &mut enums);
+        }
+    }
+    for (name, var) in &interface.vars {
+        collect_enums(var, &camel_case(name), &mut enums);
+    }
+    for (enum_name, values) in &enums {
+        write_enum(out, "    ", enum_name, values)?;
+    }
+
+    for (name, cmd) in &interface.cmds {
+        if cmd.arguments.is_empty() {
+            continue;
+        }
+        writeln!(out, "    /// Arguments of the command `{name}`.")?;
+        writeln!(
+            out,
+            "    #[derive(Debug, Clone, ::everestrs::serde::Serialize, ::everestrs::serde::Deserialize)]"
+        )?;
+        writeln!(out, "    #[serde(crate = \"::everestrs::serde\")]")?;
+        writeln!(out, "    pub struct {}Args {{", camel_case(name))?;
+        for (arg_name, arg) in &cmd.arguments {
+            if let Some(description) = &arg.description {
+                write_doc(out, "        ", description)?;
+            }
+            let field = field_name(arg_name);
+            if field != *arg_name {
+                writeln!(out, "        #[serde(rename = \"{arg_name}\")]")?;
+            }
+            writeln!(
+                out,
+                "        pub {field}: {},",
+                argument_type(name, arg_name, arg, types)
+            )?;
+        }
+        writeln!(out, "    }}")?;
+        writeln!(out)?;
+        write_validate(out, &format!("{}Args", camel_case(name)), &cmd.arguments)?;
+        write_value_conversions(out, "    ", &format!("{}Args", camel_case(name)))?;
+    }
+
+    writeln!(
+        out,
+        "    /// Typed client for calling the commands of `{interface_name}` on a requirement."
+    )?;
+    // Interfaces without commands have a client without methods.
+    writeln!(out, "    #[allow(dead_code)]")?;
+    writeln!(
+        out,
+        "    pub struct {client_name}<'a, R: ::everestrs::RuntimeApi = ::everestrs::Runtime> {{"
+    )?;
+    writeln!(out, "        runtime: &'a R,")?;
+    writeln!(out, "        requirement_id: &'a str,")?;
+    writeln!(out, "        index: usize,")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    impl<'a, R: ::everestrs::RuntimeApi> {client_name}<'a, R> {{"
+    )?;
+    writeln!(
+        out,
+        "        pub fn new(runtime: &'a R, requirement_id: &'a str) -> Self {{"
+    )?;
+    writeln!(
+        out,
+        "            Self::with_index(runtime, requirement_id, 0)"
+    )?;
+    writeln!(out, "        }}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "        /// Calls the commands on connection `index` of a requirement with multiple connections."
+    )?;
+    writeln!(
+        out,
+        "        pub fn with_index(runtime: &'a R, requirement_id: &'a str, index: usize) -> Self {{"
+    )?;
+    writeln!(out, "            Self {{ runtime, requirement_id, index }}")?;
+    writeln!(out, "        }}")?;
+    for (name, cmd) in &interface.cmds {
+        let result = result_type(name, cmd, types);
+        writeln!(out)?;
+        write_doc(out, "        ", &cmd.description)?;
+        if cmd.arguments.is_empty() {
+            writeln!(
+                out,
+                "        pub fn {}(&self) -> ::everestrs::Result<{result}> {{",
+                field_name(name)
+            )?;
+            writeln!(
+                out,
+                "            self.runtime.call_command_noargs(self.requirement_id, self.index, \"{name}\")"
+            )?;
+        } else {
+            writeln!(
+                out,
+                "        pub fn {}(&self, args: {}Args) -> ::everestrs::Result<{result}> {{",
+                field_name(name),
+                camel_case(name)
+            )?;
+            writeln!(
+                out,
+                "            self.runtime.call_command(self.requirement_id, self.index, \"{name}\", &args)"
+            )?;
+        }
+        writeln!(out, "        }}")?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+
+    writeln!(
+        out,
+        "    /// Typed publisher for the variables of `{interface_name}` on one of our implementations."
+    )?;
+    // Interfaces without variables have a publisher without methods.
+    writeln!(out, "    #[allow(dead_code)]")?;
+    writeln!(
+        out,
+        "    pub struct {publisher_name}<'a, R: ::everestrs::RuntimeApi = ::everestrs::Runtime> {{"
+    )?;
+    writeln!(out, "        runtime: &'a R,")?;
+    writeln!(out, "        implementation_id: &'a str,")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    impl<'a, R: ::everestrs::RuntimeApi> {publisher_name}<'a, R> {{"
+    )?;
+    writeln!(
+        out,
+        "        pub fn new(runtime: &'a R, implementation_id: &'a str) -> Self {{"
+    )?;
+    writeln!(out, "            Self {{ runtime, implementation_id }}")?;
+    writeln!(out, "        }}")?;
+    for (name, var) in &interface.vars {
+        writeln!(out)?;
+        if let Some(description) = &var.description {
+            write_doc(out, "        ", description)?;
+        }
+        writeln!(
+            out,
+            "        pub fn {}(&self, value: {}) -> ::everestrs::Result<()> {{",
+            field_name(name),
+            rust_type(var, &camel_case(name), types)
+        )?;
+        writeln!(
+            out,
+            "            self.runtime.publish_variable(self.implementation_id, \"{name}\", &value)"
+        )?;
+        writeln!(out, "        }}")?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+
+    writeln!(
+        out,
+        "    /// The commands of `{interface_name}`, to be implemented by a module providing it."
+    )?;
+    writeln!(out, "    pub trait {impl_name}: Send + Sync {{")?;
+    for (name, cmd) in &interface.cmds {
+        write_doc(out, "        ", &cmd.description)?;
+        write!(out, "        fn {}(&self", field_name(name))?;
+        for (arg_name, arg) in &cmd.arguments {
+            write!(
+                out,
+                ", {}: {}",
+                field_name(arg_name),
+                argument_type(name, arg_name, arg, types)
+            )?;
+        }
+        writeln!(
+            out,
+            ") -> ::everestrs::Result<{}>;",
+            result_type(name, cmd, types)
+        )?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+
+    writeln!(
+        out,
+        "    /// Extracts the arguments of the command `name` from `parameters` and calls the matching method of `service`."
+    )?;
+    writeln!(
+        out,
+        "    #[allow(unused_variables, clippy::match_single_binding)]"
+    )?;
+    writeln!(out, "    pub fn dispatch_command(")?;
+    writeln!(out, "        service: &dyn {impl_name},")?;
+    writeln!(out, "        implementation_id: &str,")?;
+    writeln!(out, "        name: &str,")?;
+    writeln!(
+        out,
+        "        parameters: ::std::collections::HashMap<String, ::everestrs::serde_json::Value>,"
+    )?;
+    writeln!(
+        out,
+        "    ) -> ::everestrs::Result<::everestrs::serde_json::Value> {{"
+    )?;
+    writeln!(out, "        match name {{")?;
+    for (name, cmd) in &interface.cmds {
+        writeln!(out, "            \"{name}\" => {{")?;
+        if !cmd.arguments.is_empty() {
+            writeln!(
+                out,
+                "                let args = {}Args {{",
+                camel_case(name)
+            )?;
+            for (arg_name, arg) in &cmd.arguments {
+                let extract = if is_nullable(arg) {
+                    "optional_arg"
+                } else {
+                    "required_arg"
+                };
+                writeln!(
+                    out,
+                    "                    {}: ::everestrs::{extract}(&parameters, \"{arg_name}\")?,",
+                    field_name(arg_name)
+                )?;
+            }
+            writeln!(out, "                }};")?;
+            writeln!(out, "                args.validate()?;")?;
+        }
+        let args: Vec<String> = cmd
+            .arguments
+            .keys()
+            .map(|arg| format!("args.{}", field_name(arg)))
+            .collect();
+        writeln!(
+            out,
+            "                let result = ::everestrs::serde_json::to_value(service.{}({})?);",
+            field_name(name),
+            args.join(", ")
+        )?;
+        writeln!(
+            out,
+            "                result.map_err(|e| ::everestrs::Error::Serialization(e.to_string()))"
+        )?;
+        writeln!(out, "            }}")?;
+    }
+    writeln!(
+        out,
+        "            _ => Err(::everestrs::Error::UnhandledCommand {{ implementation_id: implementation_id.to_string(), name: name.to_string() }}),"
+    )?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+
+    writeln!(
+        out,
+        "    /// The variables of `{interface_name}`, to be received by a module requiring it. All methods default to ignoring the value."
+    )?;
+    writeln!(out, "    pub trait {subscriber_name}: Send + Sync {{")?;
+    for (name, var) in &interface.vars {
+        if let Some(description) = &var.description {
+            write_doc(out, "        ", description)?;
+        }
+        writeln!(
+            out,
+            "        fn {}(&self, _index: usize, _value: {}) -> ::everestrs::Result<()> {{",
+            field_name(name),
+            rust_type(var, &camel_case(name), types)
+        )?;
+        writeln!(out, "            Ok(())")?;
+        writeln!(out, "        }}")?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+
+    writeln!(
+        out,
+        "    /// Deserializes the variable `name` from `data` straight into its type, without going through a `serde_json::Value`, and calls the matching method of `service`. Meant to be called from `Subscriber::handle_variable_raw`."
+    )?;
+    writeln!(
+        out,
+        "    #[allow(unused_variables, clippy::match_single_binding)]"
+    )?;
+    writeln!(
+        out,
+        "    pub fn dispatch_variable(service: &dyn {subscriber_name}, index: usize, name: &str, data: &[u8]) -> ::everestrs::Result<()> {{"
+    )?;
+    writeln!(out, "        match name {{")?;
+    for (name, var) in &interface.vars {
+        writeln!(out, "            \"{name}\" => {{")?;
+        writeln!(
+            out,
+            "                let value: {} = ::everestrs::serde_json::from_slice(data).map_err(|e| ::everestrs::Error::Deserialization {{ context: format!(\"variable '{name}': {{e}}\") }})?;",
+            rust_type(var, &camel_case(name), types)
+        )?;
+        writeln!(
+            out,
+            "                service.{}(index, value)",
+            field_name(name)
+        )?;
+        writeln!(out, "            }}")?;
+    }
+    writeln!(out, "            _ => Ok(()),")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// Writes the `validate` method of the arguments struct `type_name`, which checks the numbers among
+/// `arguments` against their `minimum`, `maximum` and `multipleOf` and fails with
+/// `Error::InvalidArgument` naming the argument and the bound. The bounds of arguments with several
+/// types, i.e. numbers that may be `null`, are not parsed and hence not checked.
+fn write_validate(
+    out: &mut String,
+    type_name: &str,
+    arguments: &BTreeMap<String, Variable>,
+) -> std::fmt::Result {
+    writeln!(out, "    impl {type_name} {{")?;
+    writeln!(
+        out,
+        "        /// Checks the arguments against the bounds of their numbers in the interface."
+    )?;
+    writeln!(
+        out,
+        "        pub fn validate(&self) -> ::everestrs::Result<()> {{"
+    )?;
+    for (arg_name, arg) in arguments {
+        let (minimum, maximum, multiple_of, cast) = match &arg.arg {
+            Argument::Single(Type::Number(options)) => {
+                (options.minimum, options.maximum, options.multiple_of, "")
+            }
+            Argument::Single(Type::Integer(options)) => (
+                options.minimum,
+                options.maximum,
+                options.multiple_of,
+                " as f64",
+            ),
+            _ => continue,
+        };
+        if minimum.is_none() && maximum.is_none() && multiple_of.is_none() {
+            continue;
+        }
+        writeln!(out, "            {{")?;
+        writeln!(
+            out,
+            "                let value = self.{}{cast};",
+            field_name(arg_name)
+        )?;
+        let checks = [
+            minimum.map(|min| (format!("value < {min:?}"), format!("is less than {min}"))),
+            maximum.map(|max| (format!("value > {max:?}"), format!("is greater than {max}"))),
+            multiple_of.map(|multiple_of| {
+                // Leaves room for the rounding of i.e. 0.3 / 0.1.
+                (
+                    format!("((value / {multiple_of:?}) - (value / {multiple_of:?}).round()).abs() > 1e-9"),
+                    format!("is not a multiple of {multiple_of}"),
+                )
+            }),
+        ];
+        for (condition, message) in checks.into_iter().flatten() {
+            writeln!(out, "                if {condition} {{")?;
+            writeln!(
+                out,
+                "                    return Err(::everestrs::Error::InvalidArgument(\"{arg_name}\", format!(\"{{value}} {message}\")));"
+            )?;
+            writeln!(out, "                }}")?;
+        }
+        writeln!(out, "            }}")?;
+    }
+    writeln!(out, "            Ok(())")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)
+}
+
+fn argument_type_name(cmd_name: &str, arg_name: &str) -> String {
+    format!("{}{}", camel_case(cmd_name), camel_case(arg_name))
+}
+
+fn result_type_name(cmd_name: &str) -> String {
+    format!("{}Result", camel_case(cmd_name))
+}
+
+fn argument_type(cmd_name: &str, arg_name: &str, arg: &Variable, types: Types) -> String {
+    rust_type(arg, &argument_type_name(cmd_name, arg_name), types)
+}
+
+fn result_type(cmd_name: &str, cmd: &crate::schema::interface::Command, types: Types) -> String {
+    cmd.result
+        .as_ref()
+        .map(|result| rust_type(result, &result_type_name(cmd_name), types))
+        .unwrap_or_else(|| "()".to_string())
+}
+
+/// Returns the Rust type that a value matching `var` deserializes into. `enum_name` is the name of
+/// the enum emitted for `var` by [`collect_enums`], if it is a string restricted to an `enum`.
+/// A value that may be one type or `null` becomes an `Option`, a `$ref` to a shared type that is
+/// in `types` becomes that type.
+fn rust_type(var: &Variable, enum_name: &str, types: Types) -> String {
+    if let Some(path) = var
+        .type_ref()
+        .and_then(|reference| types.path_of(reference))
+    {
+        return path;
+    }
+    match &var.arg {
+        Argument::Single(t) => single_rust_type(t, enum_name, types),
+        Argument::Multiple(alternatives) => match alternatives.as_slice() {
+            [Type::Null, t] | [t, Type::Null] => {
+                format!("Option<{}>", single_rust_type(t, enum_name, types))
+            }
+            _ => "::everestrs::serde_json::Value".to_string(),
+        },
+    }
+}
+
+fn single_rust_type(t: &Type, enum_name: &str, types: Types) -> String {
+    match t {
+        Type::Null => "()".to_string(),
+        Type::Boolean => "bool".to_string(),
+        Type::String(options) => match &options.enum_items {
+            Some(_) => enum_name.to_string(),
+            None => "String".to_string(),
+        },
+        Type::Number(_) => "f64".to_string(),
+        Type::Integer(_) => "i64".to_string(),
+        Type::Array(options) => match &options.items {
+            Some(items) => format!("Vec<{}>", rust_type(items, enum_name, types)),
+            None => "Vec<::everestrs::serde_json::Value>".to_string(),
+        },
+        Type::Object(_) => "::everestrs::serde_json::Value".to_string(),
+    }
+}
+
+/// Whether `var` is one type or `null`, i.e. whether [`rust_type`] turns it into an `Option`.
+fn is_nullable(var: &Variable) -> bool {
+    match &var.arg {
+        Argument::Single(_) => false,
+        Argument::Multiple(types) => {
+            matches!(types.as_slice(), [Type::Null, _] | [_, Type::Null])
+        }
+    }
+}
+
+/// Records the values of `var` under `enum_name` if it is (an array of) a string restricted to an
+/// `enum`. Objects are not descended into, since they are passed around as `serde_json::Value`.
+fn collect_enums(var: &Variable, enum_name: &str, enums: &mut BTreeMap<String, Vec<String>>) {
+    match &var.arg {
+        Argument::Single(Type::String(options)) => {
+            if let Some(values) = &options.enum_items {
+                enums.insert(enum_name.to_string(), values.clone());
+            }
+        }
+        Argument::Single(Type::Array(options)) => {
+            if let Some(items) = &options.items {
+                collect_enums(items, enum_name, enums);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn write_enum(
+    out: &mut String,
+    indent: &str,
+    enum_name: &str,
+    values: &[String],
+) -> std::fmt::Result {
+    writeln!(
+        out,
+        "{indent}#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ::everestrs::serde::Serialize, ::everestrs::serde::Deserialize)]"
+    )?;
+    writeln!(out, "{indent}#[serde(crate = \"::everestrs::serde\")]")?;
+    writeln!(out, "{indent}pub enum {enum_name} {{")?;
+    for value in values {
+        writeln!(out, "{indent}    #[serde(rename = {value:?})]")?;
+        writeln!(out, "{indent}    {},", variant_name(value))?;
+    }
+    writeln!(out, "{indent}}}")?;
+    writeln!(out)?;
+    write_value_conversions(out, indent, enum_name)
+}
+
+/// Writes the conversions of the generated type `type_name` from and into a
+/// `serde_json::Value`, for code that deals with values of any type.
+fn write_value_conversions(out: &mut String, indent: &str, type_name: &str) -> std::fmt::Result {
+    writeln!(
+        out,
+        "{indent}impl ::std::convert::TryFrom<::everestrs::serde_json::Value> for {type_name} {{"
+    )?;
+    writeln!(out, "{indent}    type Error = ::everestrs::Error;")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{indent}    fn try_from(value: ::everestrs::serde_json::Value) -> ::everestrs::Result<Self> {{"
+    )?;
+    writeln!(out, "{indent}        ::everestrs::from_value(value)")?;
+    writeln!(out, "{indent}    }}")?;
+    writeln!(out, "{indent}}}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{indent}impl From<{type_name}> for ::everestrs::serde_json::Value {{"
+    )?;
+    writeln!(out, "{indent}    fn from(value: {type_name}) -> Self {{")?;
+    writeln!(
+        out,
+        "{indent}        ::everestrs::to_value(&value).expect(\"Generated types always serialize.\")"
+    )?;
+    writeln!(out, "{indent}    }}")?;
+    writeln!(out, "{indent}}}")?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Turns an enum value like `Charging_Paused` into `ChargingPaused`, prefixing it if it would not
+/// be a valid identifier otherwise.
+fn variant_name(value: &str) -> String {
+    let name = camel_case(value);
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("Value{name}"),
+    }
+}
+
+fn write_doc(out: &mut String, indent: &str, description: &str) -> std::fmt::Result {
+    for line in description.trim().lines() {
+        writeln!(out, "{indent}/// {}", line.trim())?;
+    }
+    Ok(())
+}
+
+/// Turns an EVerest name like `power_meter` into `PowerMeter`.
+fn camel_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Turns the name of a command, variable, argument or property like `energy_Wh_import`, `connectorId`, `type` or
+/// `2nd_value` into a snake case Rust identifier, like `energy_wh_import`, `connector_id`, `type_`
+/// or `_2nd_value`. The JSON keys stay as they are, so where they differ, the generated code
+/// renames them.
+fn field_name(name: &str) -> String {
+    let mut field = String::new();
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c.is_ascii_uppercase()
+            && previous.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit())
+        {
+            field.push('_');
+        }
+        field.push(if c.is_ascii_alphanumeric() {
+            c.to_ascii_lowercase()
+        } else {
+            '_'
+        });
+        previous = Some(c);
+    }
+    if field.is_empty() || field.starts_with(|c: char| c.is_ascii_digit()) {
+        field.insert(0, '_');
+    }
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "box", "break", "const", "continue", "crate", "do", "dyn", "else",
+        "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+        "move", "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait",
+        "true", "try", "type", "unsafe", "use", "where", "while", "yield",
+    ];
+    if KEYWORDS.contains(&field.as_str()) {
+        format!("{field}_")
+    } else {
+        field
+    }
+}
+
+/// Turns an EVerest interface name into a valid Rust module name.
+fn module_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_name_sanitizes_invalid_identifiers() {
+        assert_eq!(field_name("type"), "type_");
+        assert_eq!(field_name("2nd_value"), "_2nd_value");
+        assert_eq!(field_name("connectorId"), "connector_id");
+    }
+
+    #[test]
+    fn renamed_fields_keep_their_json_keys() {
+        let interface = serde_json::json!({
+            "description": "Renames",
+            "cmds": {
+                "set": {
+                    "description": "Sets the values",
+                    "arguments": {
+                        "type": {"type": "string", "description": "The type"},
+                        "2nd_value": {"type": "number", "description": "The second value"}
+                    }
+                }
+            }
+        });
+        let out = emit_interface("renames", &interface.to_string()).unwrap();
+        assert!(
+            out.contains("#[serde(rename = \"type\")]\n        pub type_: String,"),
+            "{out}"
+        );
+        assert!(
+            out.contains("#[serde(rename = \"2nd_value\")]\n        pub _2nd_value: f64,"),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn clients_and_publishers_are_generic_over_the_runtime() {
+        let interface = serde_json::json!({
+            "description": "A meter",
+            "cmds": {"reset": {"description": "Resets the meter"}},
+            "vars": {"power": {"description": "The power", "type": "number"}}
+        });
+        let out = emit_interface("meter", &interface.to_string()).unwrap();
+        for name in ["MeterClient", "MeterPublisher"] {
+            assert!(
+                out.contains(&format!(
+                    "pub struct {name}<'a, R: ::everestrs::RuntimeApi = ::everestrs::Runtime> {{\n        runtime: &'a R,"
+                )),
+                "{out}"
+            );
+            assert!(
+                out.contains(&format!(
+                    "impl<'a, R: ::everestrs::RuntimeApi> {name}<'a, R> {{"
+                )),
+                "{out}"
+            );
+        }
+    }
+
+    #[test]
+    fn keyword_names_become_valid_methods() {
+        let interface = serde_json::json!({
+            "description": "Keywords",
+            "cmds": {
+                "match": {"description": "Matches"},
+                "set-limit": {
+                    "description": "Sets the limit",
+                    "arguments": {"limit": {"type": "number", "description": "The limit"}}
+                }
+            },
+            "vars": {"type": {"description": "The type", "type": "string"}}
+        });
+        let out = emit_interface("keywords", &interface.to_string()).unwrap();
+        for expected in [
+            "pub fn match_(&self) -> ::everestrs::Result<()> {",
+            "self.runtime.call_command_noargs(self.requirement_id, self.index, \"match\")",
+            "pub fn set_limit(&self, args: SetLimitArgs) -> ::everestrs::Result<()> {",
+            "fn match_(&self) -> ::everestrs::Result<()>;",
+            "\"set-limit\" => {",
+            "service.set_limit(args.limit)?",
+            "pub fn type_(&self, value: String) -> ::everestrs::Result<()> {",
+            "self.runtime.publish_variable(self.implementation_id, \"type\", &value)",
+            "fn type_(&self, _index: usize, _value: String) -> ::everestrs::Result<()> {",
+            "service.type_(index, value)",
+        ] {
+            assert!(out.contains(expected), "missing {expected:?} in {out}");
+        }
+    }
+
+    #[test]
+    fn config_entries_become_fields() {
+        let manifest = serde_json::json!({
+            "description": "A module",
+            "config": {
+                "max-current": {"type": "number", "description": "The current", "default": 16},
+                "type": {"type": "string", "description": "The type"},
+                "2nd_value": {"type": "integer", "description": "The value", "default": 2}
+            },
+            "provides": {},
+            "metadata": {"license": "Apache-2.0", "authors": ["everest"]}
+        });
+        let out = emit_module(&manifest.to_string(), |name| {
+            panic!("unexpected interface {name}")
+        })
+        .unwrap();
+        for expected in [
+            "#[serde(rename = \"max-current\")]\n    #[serde(default = \"ModuleConfig::default_max_current\")]\n    pub max_current: f64,",
+            "#[serde(rename = \"type\")]\n    pub type_: String,",
+            "#[serde(rename = \"2nd_value\")]\n    #[serde(default = \"ModuleConfig::default_2nd_value\")]\n    pub _2nd_value: i64,",
+            "fn default_max_current() -> f64 {",
+            "fn default_2nd_value() -> i64 {",
+        ] {
+            assert!(out.contains(expected), "{expected}: {out}");
+        }
+    }
+
+    #[test]
+    fn args_validate_the_bounds_of_numbers() {
+        let interface = serde_json::json!({
+            "description": "Limits",
+            "cmds": {
+                "set_limits": {
+                    "description": "Sets the limits",
+                    "arguments": {
+                        "current": {
+                            "type": "number",
+                            "description": "The current in A",
+                            "minimum": 0,
+                            "maximum": 32,
+                            "multipleOf": 0.1
+                        },
+                        "phases": {"type": "integer", "description": "The phases", "minimum": 1},
+                        "name": {"type": "string", "description": "Not a number"}
+                    }
+                }
+            }
+        });
+        let out = emit_interface("limits", &interface.to_string()).unwrap();
+        let start = out.find("impl SetLimitsArgs {").expect(&out);
+        let validate = &out[start..start + out[start..].find("\n    }\n").unwrap()];
+        for check in [
+            "let value = self.current;",
+            "if value < 0.0 {",
+            "if value > 32.0 {",
+            "((value / 0.1) - (value / 0.1).round()).abs() > 1e-9",
+            "InvalidArgument(\"current\", format!(\"{value} is greater than 32\"))",
+            "let value = self.phases as f64;",
+            "InvalidArgument(\"phases\", format!(\"{value} is less than 1\"))",
+        ] {
+            assert!(validate.contains(check), "{check}: {validate}");
+        }
+        assert!(!validate.contains("self.name"), "{validate}");
+        assert!(out.contains("args.validate()?;"), "{out}");
+    }
+}
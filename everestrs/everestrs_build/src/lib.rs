@@ -0,0 +1,169 @@
+//! The parts of everestrs that do not need the EVerest C++ libraries: The types of the manifest
+//! and interface definitions and the generation of Rust code from them. This is a separate crate
+//! so that build scripts can generate code without linking against libframework. `everestrs`
+//! re-exports [`codegen`] and [`schema`].
+pub mod codegen;
+pub mod schema;
+
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("could not deserialize: {context}")]
+    Deserialization { context: String },
+    #[error("could not access '{}': {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// Generates the Rust code for all interface definitions (`*.yaml`, `*.yml` or `*.json`) in
+/// `interface_dir` and writes it to `out`. Every interface becomes a module named after its file,
+/// see [`codegen::emit_interface`] for what it contains.
+///
+/// This is meant to be called from a `build.rs`, which also tells cargo to rerun it when the
+/// interfaces change:
+///
+/// ```no_run
+/// let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("interfaces.rs");
+/// everestrs_build::generate(std::path::Path::new("interfaces"), &out).unwrap();
+/// ```
+///
+/// The crate then includes the generated code with
+/// `include!(concat!(env!("OUT_DIR"), "/interfaces.rs"));` and needs to depend on `everestrs`,
+/// which the generated code refers to. Values that refer to shared types with `$ref` stay
+/// `serde_json::Value`s, see [`generate_with_types`].
+pub fn generate(interface_dir: &Path, out: &Path) -> Result<()> {
+    let interfaces = read_definitions(interface_dir)?;
+    write_generated(out, &interfaces, &BTreeMap::new())?;
+    println!("cargo:rerun-if-changed={}", interface_dir.display());
+    Ok(())
+}
+
+/// Like [`generate`], but also generates the shared types in the type files in `types_dir`, which
+/// is the `types` directory of EVerest next to `interfaces`. They end up in a module `types`
+/// with one module per type file, i.e. the `$ref` `/powermeter#/Powermeter` becomes
+/// `types::powermeter::Powermeter`, and the interfaces use them instead of `serde_json::Value`.
+/// Two interfaces referring to the same type hence share one Rust type.
+pub fn generate_with_types(interface_dir: &Path, types_dir: &Path, out: &Path) -> Result<()> {
+    let interfaces = read_definitions(interface_dir)?;
+    let types = read_definitions(types_dir)?;
+    write_generated(out, &interfaces, &types)?;
+    println!("cargo:rerun-if-changed={}", interface_dir.display());
+    println!("cargo:rerun-if-changed={}", types_dir.display());
+    Ok(())
+}
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> Error {
+    let path = path.to_path_buf();
+    move |source| Error::Io { path, source }
+}
+
+/// Reads all definitions (`*.yaml`, `*.yml` or `*.json`) in `dir`, by the names of their files.
+fn read_definitions<T: DeserializeOwned>(dir: &Path) -> Result<BTreeMap<String, T>> {
+    let mut definitions = BTreeMap::new();
+    for entry in std::fs::read_dir(dir).map_err(io_error(dir))? {
+        let path = entry.map_err(io_error(dir))?.path();
+        let is_definition = path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml" || ext == "json");
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if !is_definition {
+            continue;
+        }
+        let content = std::fs::read_to_string(&path).map_err(io_error(&path))?;
+        // YAML is a superset of JSON, so this reads both.
+        let definition: T = serde_yaml::from_str(&content).map_err(|e| Error::Deserialization {
+            context: format!("{}: {e}", path.display()),
+        })?;
+        definitions.insert(name.to_string(), definition);
+    }
+    Ok(definitions)
+}
+
+fn write_generated(
+    out: &Path,
+    interfaces: &BTreeMap<String, schema::Interface>,
+    types: &BTreeMap<String, schema::DataTypes>,
+) -> Result<()> {
+    let mut code = String::new();
+    if !types.is_empty() {
+        codegen::write_types(&mut code, types).expect("Writing to a String cannot fail.");
+        writeln!(code).expect("Writing to a String cannot fail.");
+    }
+    for (name, interface) in interfaces {
+        codegen::write_interface(
+            &mut code,
+            name,
+            interface,
+            codegen::Types::of_interfaces(types),
+        )
+        .expect("Writing to a String cannot fail.");
+        writeln!(code).expect("Writing to a String cannot fail.");
+    }
+    std::fs::write(out, code).map_err(io_error(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_type_is_generated_once() {
+        let dir = std::env::temp_dir().join(format!("everestrs_build_{}", std::process::id()));
+        let (interface_dir, types_dir) = (dir.join("interfaces"), dir.join("types"));
+        std::fs::create_dir_all(&interface_dir).unwrap();
+        std::fs::create_dir_all(&types_dir).unwrap();
+        let types = serde_json::json!({
+            "description": "Power meter types",
+            "types": {
+                "Powermeter": {
+                    "description": "A measurement",
+                    "type": "object",
+                    "required": ["timestamp"],
+                    "properties": {"timestamp": {"type": "string"}}
+                }
+            }
+        });
+        std::fs::write(types_dir.join("powermeter.json"), types.to_string()).unwrap();
+        for name in ["meter_a", "meter_b"] {
+            let interface = serde_json::json!({
+                "description": name,
+                "vars": {
+                    "powermeter": {"type": "object", "$ref": "/powermeter#/Powermeter"}
+                }
+            });
+            std::fs::write(
+                interface_dir.join(format!("{name}.json")),
+                interface.to_string(),
+            )
+            .unwrap();
+        }
+
+        let out = dir.join("interfaces.rs");
+        generate_with_types(&interface_dir, &types_dir, &out).unwrap();
+        let code = std::fs::read_to_string(&out).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(code.matches("pub struct Powermeter ").count(), 1, "{code}");
+        for name in ["meter_a", "meter_b"] {
+            let start = code.find(&format!("pub mod {name} ")).expect(name);
+            let end = code[start + 1..]
+                .find("\npub mod ")
+                .map_or(code.len(), |end| start + 1 + end);
+            assert!(
+                code[start..end].contains("super::types::powermeter::Powermeter"),
+                "{code}"
+            );
+        }
+    }
+}
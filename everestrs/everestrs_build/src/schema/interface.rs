@@ -5,7 +5,11 @@ use std::collections::BTreeMap;
 pub struct Interface {
     pub description: String,
     #[serde(default)]
+    pub version: Option<String>,
+    /// Empty for interfaces that only have variables, which may leave out `cmds`.
+    #[serde(default)]
     pub cmds: BTreeMap<String, Command>,
+    /// Empty for interfaces that only have commands, which may leave out `vars`.
     #[serde(default)]
     pub vars: BTreeMap<String, Variable>,
 }
@@ -13,6 +17,7 @@ pub struct Interface {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Command {
     pub description: String,
+    /// The interface schema forbids a `default` for arguments, so there is none to parse.
     #[serde(default)]
     pub arguments: BTreeMap<String, Variable>,
     pub result: Option<Variable>,
@@ -21,6 +26,9 @@ pub struct Command {
 #[derive(Debug, Serialize)]
 pub struct Variable {
     pub description: Option<String>,
+    /// The unit of the value, i.e. `"W"`. Not part of the interface schema, which allows any
+    /// additional entries, but some interfaces declare it.
+    pub unit: Option<String>,
     pub arg: Argument,
 }
 
@@ -35,12 +43,16 @@ pub enum Argument {
 pub struct NumberOptions {
     pub minimum: Option<f64>,
     pub maximum: Option<f64>,
+    #[serde(rename = "multipleOf")]
+    pub multiple_of: Option<f64>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct IntegerOptions {
     pub minimum: Option<f64>,
     pub maximum: Option<f64>,
+    #[serde(rename = "multipleOf")]
+    pub multiple_of: Option<f64>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -100,14 +112,25 @@ pub enum Type {
     Object(ObjectOptions),
 }
 
+impl Variable {
+    /// Returns the `$ref` to a shared type of an object or string, see [`super::TypeRef`].
+    pub fn type_ref(&self) -> Option<&str> {
+        match &self.arg {
+            Argument::Single(Type::Object(options)) => options.object_reference.as_deref(),
+            Argument::Single(Type::String(options)) => options.object_reference.as_deref(),
+            _ => None,
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for Variable {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
         let serde_json::Value::Object(mut map) = Deserialize::deserialize(deserializer)? else {
-return Err(serde::de::Error::custom("Variable must be a mapping"));
-};
+            return Err(serde::de::Error::custom("Variable must be a mapping"));
+        };
 
         let description: Option<String> = match map.remove("description") {
             None => None,
@@ -117,6 +140,14 @@ return Err(serde::de::Error::custom("Variable must be a mapping"));
             ),
         };
 
+        let unit: Option<String> = match map.remove("unit") {
+            None => None,
+            Some(v) => Some(
+                serde_json::from_value(v)
+                    .map_err(|_| serde::de::Error::custom("'unit' is not a String'"))?,
+            ),
+        };
+
         let arg_type = map
             .remove("type")
             .ok_or(serde::de::Error::custom("Missing 'type'"))?;
@@ -146,6 +177,10 @@ return Err(serde::de::Error::custom("Variable must be a mapping"));
             }
         };
 
-        Ok(Variable { description, arg })
+        Ok(Variable {
+            description,
+            unit,
+            arg,
+        })
     }
 }
@@ -0,0 +1,62 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    pub description: String,
+    pub provides: BTreeMap<String, ProvidesEntry>,
+    #[serde(default)]
+    pub requires: BTreeMap<String, RequiresEntry>,
+    #[serde(default)]
+    pub config: BTreeMap<String, ConfigEntry>,
+    pub metadata: Metadata,
+    #[serde(default)]
+    pub enable_external_mqtt: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfigEntry {
+    #[serde(rename = "type")]
+    pub type_: ConfigType,
+    pub description: String,
+    pub default: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigType {
+    Boolean,
+    Integer,
+    Number,
+    String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct YamlData {
+    pub description: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProvidesEntry {
+    pub interface: String,
+    pub description: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequiresEntry {
+    pub interface: String,
+    #[serde(default = "default_connections")]
+    pub min_connections: usize,
+    #[serde(default = "default_connections")]
+    pub max_connections: usize,
+}
+
+fn default_connections() -> usize {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Metadata {
+    pub license: String,
+    pub authors: Vec<String>,
+}
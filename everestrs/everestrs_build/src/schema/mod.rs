@@ -0,0 +1,47 @@
+pub mod interface;
+pub mod manifest;
+mod validate;
+
+// We ignore unknown fields everywhere, because the linting and verification of the YAMLs is done
+// by libframework.so. So we only mention the fields we actually care about.
+
+pub use interface::Interface;
+pub use manifest::Manifest;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// A file of shared type definitions, like `types/powermeter.yaml`, that interfaces and other
+/// types reference with `$ref`.
+#[derive(Debug, Deserialize)]
+pub struct DataTypes {
+    pub description: String,
+    pub types: BTreeMap<String, interface::Variable>,
+}
+
+/// A parsed `$ref` to a shared type, i.e. `/powermeter#/Powermeter` refers to the type
+/// `Powermeter` in `types/powermeter.yaml`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeRef<'a> {
+    /// The name of the type file, without its extension.
+    pub file: &'a str,
+    pub name: &'a str,
+}
+
+impl<'a> TypeRef<'a> {
+    /// Returns `None` if `reference` does not have the form `/<file>#/<name>`.
+    pub fn parse(reference: &'a str) -> Option<Self> {
+        let (file, name) = reference.strip_prefix('/')?.split_once("#/")?;
+        if file.is_empty() || name.is_empty() {
+            return None;
+        }
+        Some(Self { file, name })
+    }
+
+    /// Looks up the definition this refers to in `types`, the type files by name.
+    pub fn resolve<'t>(
+        &self,
+        types: &'t BTreeMap<String, DataTypes>,
+    ) -> Option<&'t interface::Variable> {
+        types.get(self.file)?.types.get(self.name)
+    }
+}
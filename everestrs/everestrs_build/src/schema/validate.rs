@@ -0,0 +1,138 @@
+use super::interface::{Argument, Type, Variable};
+use serde_json::Value;
+
+impl Variable {
+    /// Checks `value` against this definition and returns a description of the first violation.
+    ///
+    /// This only covers what we parse of the JSON schema: Types, enums, lengths, bounds, array
+    /// items, `multipleOf` and the required and declared properties of objects. Patterns,
+    /// formats, `$ref`s and undeclared properties are not checked, libframework still validates
+    /// those when publishing.
+    pub fn validate(&self, value: &Value) -> Result<(), String> {
+        validate(self, value, "$")
+    }
+}
+
+fn validate(var: &Variable, value: &Value, path: &str) -> Result<(), String> {
+    match &var.arg {
+        Argument::Single(t) => validate_type(t, value, path),
+        Argument::Multiple(types) => {
+            if types.iter().any(|t| validate_type(t, value, path).is_ok()) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "`{path}`: {value} matches none of the allowed types"
+                ))
+            }
+        }
+    }
+}
+
+fn validate_type(t: &Type, value: &Value, path: &str) -> Result<(), String> {
+    let violation = |what: String| Err(format!("`{path}`: {what}"));
+    match (t, value) {
+        (Type::Null, Value::Null) | (Type::Boolean, Value::Bool(_)) => Ok(()),
+        (Type::String(options), Value::String(s)) => {
+            if let Some(values) = &options.enum_items {
+                if !values.contains(s) {
+                    return violation(format!("'{s}' is not one of {values:?}"));
+                }
+            }
+            let len = s.chars().count();
+            match (options.min_length, options.max_length) {
+                (Some(min), _) if len < min => violation(format!("'{s}' is shorter than {min}")),
+                (_, Some(max)) if len > max => violation(format!("'{s}' is longer than {max}")),
+                _ => Ok(()),
+            }
+        }
+        (Type::Number(options), Value::Number(n)) => {
+            validate_bounds(n.as_f64(), options.minimum, options.maximum, path)?;
+            validate_multiple_of(n.as_f64(), options.multiple_of, path)
+        }
+        (Type::Integer(options), Value::Number(n)) => {
+            if !(n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|f| f.fract() == 0.)) {
+                return violation(format!("{n} is not an integer"));
+            }
+            validate_bounds(n.as_f64(), options.minimum, options.maximum, path)?;
+            validate_multiple_of(n.as_f64(), options.multiple_of, path)
+        }
+        (Type::Array(options), Value::Array(items)) => {
+            if let Some(min) = options.min_items.filter(|min| items.len() < *min) {
+                return violation(format!("fewer than {min} items"));
+            }
+            if let Some(max) = options.max_items.filter(|max| items.len() > *max) {
+                return violation(format!("more than {max} items"));
+            }
+            if let Some(item) = &options.items {
+                for (i, value) in items.iter().enumerate() {
+                    validate(item, value, &format!("{path}[{i}]"))?;
+                }
+            }
+            Ok(())
+        }
+        (Type::Object(options), Value::Object(map)) => {
+            // We cannot resolve references, libframework checks those.
+            if options.object_reference.is_some() {
+                return Ok(());
+            }
+            for name in &options.required {
+                if !map.contains_key(name) {
+                    return violation(format!("missing required property '{name}'"));
+                }
+            }
+            for (name, property) in &options.properties {
+                if let Some(value) = map.get(name) {
+                    validate(property, value, &format!("{path}.{name}"))?;
+                }
+            }
+            Ok(())
+        }
+        // A string referencing another type is not necessarily a string.
+        (Type::String(options), _) if options.object_reference.is_some() => Ok(()),
+        _ => violation(format!("{value} is not of type {}", type_name(t))),
+    }
+}
+
+fn validate_bounds(
+    n: Option<f64>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    path: &str,
+) -> Result<(), String> {
+    let Some(n) = n else {
+        return Ok(());
+    };
+    match (minimum, maximum) {
+        (Some(min), _) if n < min => Err(format!("`{path}`: {n} is less than {min}")),
+        (_, Some(max)) if n > max => Err(format!("`{path}`: {n} is greater than {max}")),
+        _ => Ok(()),
+    }
+}
+
+fn validate_multiple_of(
+    n: Option<f64>,
+    multiple_of: Option<f64>,
+    path: &str,
+) -> Result<(), String> {
+    let (Some(n), Some(multiple_of)) = (n, multiple_of) else {
+        return Ok(());
+    };
+    // Leaves room for the rounding of i.e. 0.3 / 0.1.
+    let quotient = n / multiple_of;
+    if (quotient - quotient.round()).abs() > 1e-9 {
+        return Err(format!("`{path}`: {n} is not a multiple of {multiple_of}"));
+    }
+    Ok(())
+}
+
+fn type_name(t: &Type) -> &'static str {
+    match t {
+        Type::Null => "null",
+        Type::Boolean => "boolean",
+        Type::String(_) => "string",
+        Type::Number(_) => "number",
+        Type::Integer(_) => "integer",
+        Type::Array(_) => "array",
+        Type::Object(_) => "object",
+    }
+}
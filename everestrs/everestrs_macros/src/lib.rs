@@ -0,0 +1,276 @@
+//! The procedural macros of everestrs, which re-exports them. See [`macro@subscriber`].
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::{parse_macro_input, Attribute, FnArg, Ident, ImplItem, ImplItemFn, ItemImpl, LitStr};
+use syn::{Pat, Type};
+
+/// Implements `everestrs::Subscriber` for the type of an inherent `impl` block by dispatching to
+/// its methods marked with `#[command(..)]` and `#[variable(..)]`, instead of matching on the
+/// names in a hand written `handle_command`.
+///
+/// ```ignore
+/// #[everestrs::subscriber]
+/// impl Auth {
+///     #[command(impl_id = "auth", name = "validate")]
+///     fn validate(&self, token: String, connector: Option<i64>) -> everestrs::Result<bool> {
+///         ..
+///     }
+///
+///     #[variable(requirement_id = "meter", name = "powermeter")]
+///     fn on_powermeter(&self, index: usize, value: PowerMeter) -> everestrs::Result<()> {
+///         ..
+///     }
+/// }
+/// ```
+///
+/// `name` defaults to the name of the method. The arguments of a command method are extracted
+/// by their name with `everestrs::required_arg`, or `everestrs::optional_arg` for `Option`s, and
+/// its result is serialized as the result of the command. A variable method gets the value and,
+/// if it takes two arguments, the connection index before it. Only the marked variables are
+/// subscribed to. The other hooks of `Subscriber` keep their defaults, so a type that needs them
+/// implements the trait by hand.
+#[proc_macro_attribute]
+pub fn subscriber(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(Span::call_site(), "#[subscriber] takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+    let mut item = parse_macro_input!(item as ItemImpl);
+    match expand(&mut item) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A method marked with `#[command(..)]` or `#[variable(..)]`.
+struct Handler {
+    /// The implementation id for commands, the requirement id for variables.
+    id: LitStr,
+    name: LitStr,
+    method: Ident,
+    /// The typed arguments of the method, without `self`.
+    args: Vec<(Ident, Type)>,
+}
+
+fn expand(item: &mut ItemImpl) -> syn::Result<TokenStream2> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(syn::Error::new_spanned(
+            path,
+            "#[subscriber] goes on an inherent impl block",
+        ));
+    }
+    let mut commands = Vec::new();
+    let mut variables = Vec::new();
+    for impl_item in &mut item.items {
+        let ImplItem::Fn(method) = impl_item else {
+            continue;
+        };
+        let mut attrs = Vec::new();
+        for attr in std::mem::take(&mut method.attrs) {
+            if attr.path().is_ident("command") {
+                commands.push(handler(&attr, "impl_id", method)?);
+            } else if attr.path().is_ident("variable") {
+                variables.push(handler(&attr, "requirement_id", method)?);
+            } else {
+                attrs.push(attr);
+            }
+        }
+        method.attrs = attrs;
+    }
+    check_unique(&commands)?;
+    check_unique(&variables)?;
+
+    let command_arms = commands.iter().map(command_arm);
+    let variable_arms = variables
+        .iter()
+        .map(variable_arm)
+        .collect::<syn::Result<Vec<_>>>()?;
+    let variable_ids = variables.iter().map(|handler| &handler.id);
+    let variable_names = variables.iter().map(|handler| &handler.name);
+
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        #item
+
+        impl #impl_generics ::everestrs::Subscriber for #self_ty #where_clause {
+            #[allow(unused_variables, clippy::match_single_binding)]
+            fn handle_command(
+                &self,
+                implementation_id: &str,
+                name: &str,
+                parameters: ::std::collections::HashMap<String, ::everestrs::serde_json::Value>,
+            ) -> ::everestrs::Result<::everestrs::serde_json::Value> {
+                match (implementation_id, name) {
+                    #(#command_arms)*
+                    _ => Err(::everestrs::Error::UnhandledCommand {
+                        implementation_id: implementation_id.to_string(),
+                        name: name.to_string(),
+                    }),
+                }
+            }
+
+            #[allow(unused_variables, clippy::match_single_binding)]
+            fn handle_variable(
+                &self,
+                implementation_id: &str,
+                index: usize,
+                name: &str,
+                value: ::everestrs::serde_json::Value,
+            ) -> ::everestrs::Result<()> {
+                match (implementation_id, name) {
+                    #(#variable_arms)*
+                    _ => Ok(()),
+                }
+            }
+
+            #[allow(clippy::match_like_matches_macro, clippy::match_single_binding)]
+            fn subscribes_to(&self, implementation_id: &str, name: &str) -> bool {
+                match (implementation_id, name) {
+                    #((#variable_ids, #variable_names) => true,)*
+                    _ => false,
+                }
+            }
+        }
+    })
+}
+
+/// Parses `#[command(..)]` or `#[variable(..)]`, whose id is given as `id_key`, on `method`.
+fn handler(attr: &Attribute, id_key: &str, method: &ImplItemFn) -> syn::Result<Handler> {
+    let mut id: Option<LitStr> = None;
+    let mut name: Option<LitStr> = None;
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident(id_key) {
+            id = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error(format!("expected `{id_key}` or `name`")));
+        }
+        Ok(())
+    })?;
+    let id = id.ok_or_else(|| syn::Error::new_spanned(attr, format!("missing `{id_key}`")))?;
+    let method_name = &method.sig.ident;
+    let name = name.unwrap_or_else(|| LitStr::new(&method_name.unraw().to_string(), id.span()));
+
+    if method.sig.receiver().is_none() {
+        return Err(syn::Error::new_spanned(
+            &method.sig,
+            "handlers must take `&self`",
+        ));
+    }
+    let mut args = Vec::new();
+    for input in &method.sig.inputs {
+        let FnArg::Typed(arg) = input else {
+            continue;
+        };
+        let Pat::Ident(pat) = &*arg.pat else {
+            return Err(syn::Error::new_spanned(
+                &arg.pat,
+                "handler arguments must be plain names",
+            ));
+        };
+        args.push((pat.ident.clone(), (*arg.ty).clone()));
+    }
+    Ok(Handler {
+        id,
+        name,
+        method: method_name.clone(),
+        args,
+    })
+}
+
+fn check_unique(handlers: &[Handler]) -> syn::Result<()> {
+    for (i, handler) in handlers.iter().enumerate() {
+        let duplicate = handlers[..i]
+            .iter()
+            .any(|h| h.id.value() == handler.id.value() && h.name.value() == handler.name.value());
+        if duplicate {
+            return Err(syn::Error::new(
+                handler.name.span(),
+                format!(
+                    "'{}' of '{}' is handled twice",
+                    handler.name.value(),
+                    handler.id.value()
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn command_arm(handler: &Handler) -> TokenStream2 {
+    let Handler {
+        id,
+        name,
+        method,
+        args,
+    } = handler;
+    let extract = args.iter().map(|(arg, ty)| {
+        let extract = if is_option(ty) {
+            quote!(optional_arg)
+        } else {
+            quote!(required_arg)
+        };
+        let arg_name = arg.unraw().to_string();
+        quote! {
+            let #arg: #ty = ::everestrs::#extract(&parameters, #arg_name)?;
+        }
+    });
+    let arg_names = args.iter().map(|(arg, _)| arg);
+    quote! {
+        (#id, #name) => {
+            #(#extract)*
+            let result = ::everestrs::serde_json::to_value(self.#method(#(#arg_names),*)?);
+            result.map_err(|e| ::everestrs::Error::Serialization(e.to_string()))
+        }
+    }
+}
+
+fn variable_arm(handler: &Handler) -> syn::Result<TokenStream2> {
+    let Handler {
+        id,
+        name,
+        method,
+        args,
+    } = handler;
+    let ty = match args.as_slice() {
+        [(_, ty)] | [_, (_, ty)] => ty,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                method,
+                "variable handlers take the value and optionally the index before it",
+            ))
+        }
+    };
+    let call = if args.len() == 2 {
+        quote!(self.#method(index, value))
+    } else {
+        quote!(self.#method(value))
+    };
+    Ok(quote! {
+        (#id, #name) => {
+            let value: #ty = ::everestrs::serde_json::from_value(value).map_err(|e| {
+                ::everestrs::Error::Deserialization {
+                    context: format!("variable '{}' of '{}': {e}", #name, #id),
+                }
+            })?;
+            #call
+        }
+    })
+}
+
+/// Whether `ty` is spelled as an `Option`, which is all a macro can tell.
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
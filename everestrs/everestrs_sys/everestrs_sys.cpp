@@ -1,7 +1,14 @@
 #include "everestrs_sys.hpp"
 
+#include <cerrno>
+#include <chrono>
+#include <csignal>
 #include <cstdlib>
+#include <future>
 #include <stdexcept>
+#include <thread>
+
+#include <semaphore.h>
 
 #include "everestrs/lib.rs.h"
 
@@ -23,6 +30,24 @@ std::unique_ptr<Everest::Config> create_config_instance(const Everest::RuntimeSe
                                              rs.mqtt_external_prefix);
 }
 
+// libframework has no notion of command errors, so we send them to the caller wrapped in an object
+// with this single key.
+const char* const COMMAND_ERROR_KEY = "everestrs_error";
+
+// Posted by the handler of SIGINT and SIGTERM and by `Module::shutdown` to wake up
+// `Module::wait_for_shutdown_signal`. sem_post is one of the few functions that may be called from
+// a signal handler.
+sem_t shutdown_semaphore;
+std::once_flag shutdown_semaphore_init;
+
+void init_shutdown_semaphore() {
+    std::call_once(shutdown_semaphore_init, []() { sem_init(&shutdown_semaphore, 0, 0); });
+}
+
+void on_shutdown_signal(int /* signal */) {
+    sem_post(&shutdown_semaphore);
+}
+
 JsonBlob json2blob(const json& j) {
     // I did not find a way to not copy the data at least once here.
     const std::string dumped = j.dump();
@@ -46,12 +71,74 @@ JsonBlob Module::get_interface(rust::Str interface_name) const {
     return json2blob(interface_def);
 }
 
-JsonBlob Module::initialize() {
+JsonBlob Module::get_module_config() const {
+    // The config of the module itself is stored under "!module", the other keys are the configs of
+    // the implementations.
+    const json config_maps = config_->get_module_json_config(module_id_);
+    if (!config_maps.contains("!module")) {
+        return json2blob(json::object());
+    }
+    return json2blob(config_maps.at("!module"));
+}
+
+JsonBlob Module::get_manifest() const {
+    const std::string module_name = config_->get_main_config().at(module_id_).at("module");
+    return json2blob(config_->get_manifests().at(module_name));
+}
+
+void Module::initialize(const Runtime& rt) const {
+    handle_->register_connection_handlers([&rt]() { rt.on_disconnected(); }, [&rt]() { rt.on_reconnected(); });
+
+    const json connections = config_->get_main_config().at(module_id_).value("connections", json::object());
+    for (const auto& requirement : connections.items()) {
+        const std::string requirement_id = requirement.key();
+        for (size_t index = 0; index < requirement.value().size(); ++index) {
+            const std::string other_module_id = requirement.value().at(index).at("module_id");
+            handle_->register_module_ready_handler(other_module_id, [&rt, requirement_id, index]() {
+                rt.on_requirement_ready(requirement_id, index);
+            });
+        }
+    }
     handle_->connect();
     handle_->spawn_main_loop_thread();
+    running_ = true;
+}
 
-    const std::string& module_name = config_->get_main_config().at(module_id_).at("module");
-    return json2blob(config_->get_manifests().at(module_name));
+void Module::shutdown() const {
+    if (running_.exchange(false)) {
+        init_shutdown_semaphore();
+        sem_post(&shutdown_semaphore);
+        // The calls still waiting for their result fail right away instead of after the timeout of
+        // libframework, their result cannot arrive anymore once we are disconnected.
+        handle_->cancel_cmd_calls();
+        handle_->disconnect();
+        try {
+            handle_->wait_for_main_loop_end();
+        } catch (const std::exception& e) {
+            // The main loop reports a lost connection as an exception, which is expected here.
+            EVLOG_debug << "Main loop ended with: " << e.what();
+        }
+    }
+
+    // A second caller of shutdown must not return before the first one has seen the calls end, the
+    // threads of call_command_with_timeout use this Module until then.
+    std::unique_lock<std::mutex> lock(calls_mutex_);
+    calls_cv_.wait(lock, [this]() { return calls_in_flight_ == 0; });
+}
+
+void Module::wait_for_shutdown_signal() const {
+    init_shutdown_semaphore();
+    struct sigaction action {};
+    action.sa_handler = on_shutdown_signal;
+    sigemptyset(&action.sa_mask);
+    sigaction(SIGINT, &action, nullptr);
+    sigaction(SIGTERM, &action, nullptr);
+
+    if (!running_) {
+        return;
+    }
+    while (sem_wait(&shutdown_semaphore) == -1 && errno == EINTR) {
+    }
 }
 
 void Module::signal_ready(const Runtime& rt) const {
@@ -59,13 +146,157 @@ void Module::signal_ready(const Runtime& rt) const {
     handle_->signal_ready();
 }
 
-void Module::provide_command(const Runtime& rt, const CommandMeta& meta) const {
-    handle_->provide_cmd(std::string(meta.implementation_id), std::string(meta.name), [&rt, meta](json args) {
-        JsonBlob blob = rt.handle_command(meta, json2blob(args));
-        return json::parse(blob.data.begin(), blob.data.end());
+void Module::provide_command(const Runtime& rt, const CommandRef& command) const {
+    handle_->provide_cmd(std::string(command.implementation_id), std::string(command.name), [&rt, command](json args) {
+        CommandResult result = rt.handle_command(command, json2blob(args));
+        json data = json::parse(result.data.data.begin(), result.data.data.end());
+        if (!result.is_error) {
+            return data;
+        }
+        // A single bad call must not take down the whole module, so we report the failure back to
+        // the caller instead. Thrown as CommandError, libframework does not validate it against the result.
+        EVLOG_error << "Command " << std::string(command.implementation_id) << "->" << std::string(command.name)
+                    << " failed: " << data.dump();
+        throw Everest::CommandError(json::object({{COMMAND_ERROR_KEY, data}}));
+    });
+}
+
+size_t Module::subscribe_variable(const Runtime& rt, const VariableRef& variable) const {
+    const Requirement req(std::string(variable.requirement_id), variable.index);
+    auto token = handle_->subscribe_var(req, std::string(variable.name), [&rt, variable](json args) {
+        rt.handle_variable(variable, json2blob(args));
+    });
+    std::lock_guard<std::mutex> lock(subscriptions_mutex_);
+    const size_t subscription_id = next_subscription_id_++;
+    subscriptions_.emplace(subscription_id, std::move(token));
+    return subscription_id;
+}
+
+void Module::unsubscribe_variable(size_t subscription_id) const {
+    UnsubscribeToken token;
+    {
+        std::lock_guard<std::mutex> lock(subscriptions_mutex_);
+        const auto it = subscriptions_.find(subscription_id);
+        if (it == subscriptions_.end()) {
+            return;
+        }
+        token = std::move(it->second);
+        subscriptions_.erase(it);
+    }
+    token();
+}
+
+JsonBlob Module::get_latest_variable(rust::Str requirement_id, size_t index, rust::Str name) const {
+    const auto value = handle_->get_latest_var(Requirement(std::string(requirement_id), index), std::string(name));
+    if (!value.has_value()) {
+        return JsonBlob{};
+    }
+    return json2blob(*value);
+}
+
+void Module::publish_variable(rust::Str implementation_id, rust::Str name, rust::Slice<const uint8_t> value) const {
+    handle_->publish_var(std::string(implementation_id), std::string(name), json::parse(value.begin(), value.end()));
+}
+
+void Module::publish_variable_formatted(rust::Str implementation_id, rust::Str name,
+                                        rust::Slice<const uint8_t> value) const {
+    handle_->publish_var_formatted(std::string(implementation_id), std::string(name),
+                                   std::string(value.begin(), value.end()));
+}
+
+bool Module::is_telemetry_enabled() const {
+    return handle_->is_telemetry_enabled();
+}
+
+void Module::publish_telemetry(rust::Str category, rust::Slice<const uint8_t> data) const {
+    handle_->telemetry_publish(std::string(category), std::string(data.begin(), data.end()));
+}
+
+void Module::publish_external_mqtt(rust::Str topic, rust::Slice<const uint8_t> data) const {
+    handle_->external_mqtt_publish(std::string(topic), std::string(data.begin(), data.end()));
+}
+
+void Module::subscribe_external_mqtt(const Runtime& rt, rust::Str topic, size_t handler_id) const {
+    handle_->provide_external_mqtt_handler(std::string(topic), [&rt, handler_id](const std::string& data) {
+        rt.handle_external_mqtt(handler_id,
+                                rust::Slice<const uint8_t>(reinterpret_cast<const uint8_t*>(data.data()), data.size()));
     });
 }
 
+size_t Module::get_connection_count(rust::Str requirement_id) const {
+    const json connections = config_->get_main_config().at(module_id_).value("connections", json::object());
+    const auto it = connections.find(std::string(requirement_id));
+    if (it == connections.end()) {
+        return 0;
+    }
+    return it->size();
+}
+
+CommandResult Module::call_command(rust::Str requirement_id, size_t index, rust::Str name, JsonBlob args) const {
+    const json result = handle_->call_cmd(Requirement(std::string(requirement_id), index), std::string(name),
+                                          json::parse(args.data.begin(), args.data.end()));
+    if (result.is_object() && result.size() == 1 && result.contains(COMMAND_ERROR_KEY)) {
+        return CommandResult{true, json2blob(result.at(COMMAND_ERROR_KEY))};
+    }
+    return CommandResult{false, json2blob(result)};
+}
+
+CommandResult Module::call_command_with_timeout(rust::Str requirement_id, size_t index, rust::Str name, JsonBlob args,
+                                                uint64_t timeout_ms, bool& timed_out) const {
+    auto promise = std::make_shared<std::promise<CommandResult>>();
+    auto future = promise->get_future();
+    {
+        // Checked under the lock that shutdown waits with, so a call either is counted before
+        // shutdown waits for the calls or does not start at all.
+        std::lock_guard<std::mutex> lock(calls_mutex_);
+        if (!running_) {
+            throw std::runtime_error("The module is shutting down");
+        }
+        ++calls_in_flight_;
+    }
+    std::thread([this, promise, requirement_id = std::string(requirement_id), index, name = std::string(name),
+                 args = std::move(args)]() mutable {
+        try {
+            promise->set_value(call_command(requirement_id, index, name, std::move(args)));
+        } catch (...) {
+            promise->set_exception(std::current_exception());
+        }
+        std::lock_guard<std::mutex> lock(calls_mutex_);
+        --calls_in_flight_;
+        calls_cv_.notify_all();
+    }).detach();
+
+    timed_out = future.wait_for(std::chrono::milliseconds(timeout_ms)) == std::future_status::timeout;
+    if (timed_out) {
+        return CommandResult{false, JsonBlob{}};
+    }
+    return future.get();
+}
+
 std::unique_ptr<Module> create_module(rust::Str module_id, rust::Str prefix, rust::Str conf) {
     return std::make_unique<Module>(std::string(module_id), std::string(prefix), std::string(conf));
 }
+
+void log_message(LogLevel level, rust::Str message) {
+    const std::string msg(message);
+    switch (level) {
+    case LogLevel::Verbose:
+        EVLOG_verbose << msg;
+        break;
+    case LogLevel::Debug:
+        EVLOG_debug << msg;
+        break;
+    case LogLevel::Info:
+        EVLOG_info << msg;
+        break;
+    case LogLevel::Warning:
+        EVLOG_warning << msg;
+        break;
+    case LogLevel::Error:
+        EVLOG_error << msg;
+        break;
+    case LogLevel::Critical:
+        EVLOG_critical << msg;
+        break;
+    }
+}
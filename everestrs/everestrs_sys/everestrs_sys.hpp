@@ -1,33 +1,74 @@
 #pragma once
 
+#include <atomic>
+#include <condition_variable>
 #include <framework/everest.hpp>
 #include <framework/runtime.hpp>
+#include <map>
 #include <memory>
+#include <mutex>
 #include <string>
 
 #include "rust/cxx.h"
 
-struct CommandMeta;
+struct CommandRef;
+struct CommandResult;
 struct JsonBlob;
 struct Runtime;
+struct VariableRef;
+enum class LogLevel : uint8_t;
 
 class Module {
 public:
     Module(const std::string& module_id, const std::string& prefix, const std::string& conf);
 
-    JsonBlob initialize();
+    JsonBlob get_manifest() const;
+    void initialize(const Runtime& rt) const;
+    void shutdown() const;
+    void wait_for_shutdown_signal() const;
     JsonBlob get_interface(rust::Str interface_name) const;
+    JsonBlob get_module_config() const;
 
     void signal_ready(const Runtime& rt) const;
-    void provide_command(const Runtime& rt, const CommandMeta& meta) const;
+    void provide_command(const Runtime& rt, const CommandRef& command) const;
+    size_t subscribe_variable(const Runtime& rt, const VariableRef& variable) const;
+    void unsubscribe_variable(size_t subscription_id) const;
+    size_t get_connection_count(rust::Str requirement_id) const;
+    CommandResult call_command(rust::Str requirement_id, size_t index, rust::Str name, JsonBlob args) const;
+    CommandResult call_command_with_timeout(rust::Str requirement_id, size_t index, rust::Str name, JsonBlob args,
+                                            uint64_t timeout_ms, bool& timed_out) const;
+    JsonBlob get_latest_variable(rust::Str requirement_id, size_t index, rust::Str name) const;
+    void publish_variable(rust::Str implementation_id, rust::Str name, rust::Slice<const uint8_t> value) const;
+    void publish_variable_formatted(rust::Str implementation_id, rust::Str name,
+                                    rust::Slice<const uint8_t> value) const;
+    bool is_telemetry_enabled() const;
+    void publish_telemetry(rust::Str category, rust::Slice<const uint8_t> data) const;
+    void publish_external_mqtt(rust::Str topic, rust::Slice<const uint8_t> data) const;
+    void subscribe_external_mqtt(const Runtime& rt, rust::Str topic, size_t handler_id) const;
 
-    // TODO(hrapp): Add call_command, publish_variable and subscribe_variable.
+    // Escape hatch for Rust code that reaches libframework through `Runtime::raw_module`.
+    Everest::Everest& everest() const {
+        return *handle_;
+    }
 
 private:
     const std::string module_id_;
     Everest::RuntimeSettings rs_;
     std::unique_ptr<Everest::Config> config_;
     std::unique_ptr<Everest::Everest> handle_;
+    mutable std::atomic<bool> running_{false};
+
+    // Calls of `call_command_with_timeout` that are still running in the background. They use
+    // `handle_`, so `shutdown` waits for them.
+    mutable std::mutex calls_mutex_;
+    mutable std::condition_variable calls_cv_;
+    mutable size_t calls_in_flight_ = 0;
+
+    // The tokens of the variable subscriptions, by the id handed out by `subscribe_variable`.
+    mutable std::mutex subscriptions_mutex_;
+    mutable std::map<size_t, UnsubscribeToken> subscriptions_;
+    mutable size_t next_subscription_id_ = 0;
 };
 
 std::unique_ptr<Module> create_module(rust::Str module_name, rust::Str prefix, rust::Str conf);
+void log_message(LogLevel level, rust::Str message);
@@ -4,9 +4,13 @@
 #define FRAMEWORK_EVEREST_HPP
 
 #include <chrono>
+#include <condition_variable>
 #include <future>
 #include <map>
+#include <mutex>
+#include <optional>
 #include <set>
+#include <stdexcept>
 #include <thread>
 #include <variant>
 
@@ -28,6 +32,26 @@ struct cmd {
     ReturnType return_type; ///< The return type
 };
 
+///
+/// \brief Thrown by a command handler given to provide_cmd to fail the call with \p error instead of returning a
+/// result. The error is sent to the caller as the retval, but not validated against the result schema
+///
+class CommandError : public std::runtime_error {
+public:
+    explicit CommandError(json error) : std::runtime_error(error.dump()), error(std::move(error)) {
+    }
+
+    json error;
+};
+
+///
+/// \brief Thrown by call_cmd if the call was cancelled with Everest::cancel_cmd_calls before its result arrived
+///
+class CommandCancelled : public std::runtime_error {
+public:
+    using std::runtime_error::runtime_error;
+};
+
 using TelemetryEntry = std::variant<std::string, const char*, bool, int32_t, uint32_t, int64_t, uint64_t, double>;
 using TelemetryMap = std::map<std::string, TelemetryEntry>;
 
@@ -60,18 +84,44 @@ public:
     /// \brief Provides functionality for calling commands of other modules. The module is identified by the given \p
     /// req, the command by the given command name \p cmd_name and the needed arguments by \p args
     ///
+    /// \throws CommandCancelled if the call was cancelled with cancel_cmd_calls()
+    ///
     json call_cmd(const Requirement& req, const std::string& cmd_name, json args);
 
     ///
-    /// \brief Publishes a variable of the given \p impl_id, names \p var_name with the given \p value
+    /// \brief Makes all calls of call_cmd that are waiting for their result, and all later ones, throw
+    /// CommandCancelled instead of waiting for the result or its timeout, i.e. when shutting down
+    ///
+    void cancel_cmd_calls();
+
+    ///
+    /// \brief Publishes a variable of the given \p impl_id, names \p var_name with the given \p value. It is also
+    /// published on a topic of its own that the broker retains, see get_latest_var()
     ///
     void publish_var(const std::string& impl_id, const std::string& var_name, json value);
 
+    ///
+    /// \brief Like publish_var(), but sends the JSON in \p value as it is formatted, i.e. pretty printed or with the
+    /// keys of objects in their original order, instead of re-encoding it. It is only parsed to validate it
+    ///
+    void publish_var_formatted(const std::string& impl_id, const std::string& var_name, const std::string& value);
+
+    ///
+    /// \brief Reads the value of the variable \p var_name of another module identified by the given \p req that the
+    /// broker retained, i.e. the one published last. The first read of a variable subscribes to its retained topic and
+    /// waits up to a second for the broker to send the value, later reads return the latest value right away
+    ///
+    /// \returns the value, or nothing if none was ever published
+    ///
+    std::optional<json> get_latest_var(const Requirement& req, const std::string& var_name);
+
     ///
     /// \brief Subscribes to a variable of another module identified by the given \p req and variable name \p
     /// var_name. The given \p callback is called when a new value becomes available
     ///
-    void subscribe_var(const Requirement& req, const std::string& var_name, const JsonCallback& callback);
+    /// \returns a token that removes the subscription again
+    ///
+    UnsubscribeToken subscribe_var(const Requirement& req, const std::string& var_name, const JsonCallback& callback);
 
     ///
     /// \brief publishes the given \p data on the given \p topic
@@ -133,6 +183,20 @@ public:
     ///
     void register_on_ready_handler(const std::function<void()>& handler);
 
+    ///
+    /// \brief registers a callback \p handler that is called when the module \p module_id signals that it is ready. The
+    /// signal is not retained, so it is missed if the module signalled before we connected.
+    ///
+    void register_module_ready_handler(const std::string& module_id, const std::function<void()>& handler);
+
+    ///
+    /// \brief registers callbacks for when the connection to the MQTT broker is lost and restored, which makes the
+    /// MQTT main loop reconnect instead of failing, see MQTTAbstraction::register_connection_handlers. Must be called
+    /// before spawn_main_loop_thread()
+    ///
+    void register_connection_handlers(const std::function<void()>& on_disconnected,
+                                      const std::function<void()>& on_reconnected);
+
 private:
     MQTTAbstraction mqtt_abstraction;
     Config config;
@@ -142,6 +206,14 @@ private:
     std::chrono::seconds remote_cmd_res_timeout;
     bool validate_data_with_schema;
     std::unique_ptr<std::function<void()>> on_ready;
+    // The promises of the calls of call_cmd that wait for their result, by call id, see cancel_cmd_calls()
+    std::mutex pending_calls_mutex;
+    std::map<std::string, std::shared_ptr<std::promise<json>>> pending_calls;
+    bool cmd_calls_cancelled{false};
+    // The latest values of the variables read with get_latest_var(), by their retained topic
+    std::mutex latest_vars_mutex;
+    std::condition_variable latest_vars_cv;
+    std::map<std::string, std::optional<json>> latest_vars;
     std::thread heartbeat_thread;
     std::string module_name;
     std::future<void> main_loop_end{};
@@ -159,6 +231,10 @@ private:
 
     void publish_metadata();
 
+    void validate_var(const std::string& impl_id, const std::string& var_name, const json& value);
+
+    void send_var(const std::string& impl_id, const std::string& var_name, const std::string& var_publish_data);
+
     static std::string check_args(const Arguments& func_args, json manifest_args);
     static bool check_arg(ArgumentType arg_types, json manifest_arg);
 };
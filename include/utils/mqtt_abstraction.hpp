@@ -3,6 +3,7 @@
 #ifndef UTILS_MQTT_ABSTRACTION_HPP
 #define UTILS_MQTT_ABSTRACTION_HPP
 
+#include <functional>
 #include <future>
 
 #include <nlohmann/json.hpp>
@@ -45,6 +46,10 @@ public:
     /// \copydoc MQTTAbstractionImpl::publish(const std::string&, const json&, QOS)
     void publish(const std::string& topic, const json& json, QOS qos);
 
+    ///
+    /// \copydoc MQTTAbstractionImpl::publish(const std::string&, const json&, QOS, bool)
+    void publish(const std::string& topic, const json& json, QOS qos, bool retain);
+
     ///
     /// \copydoc MQTTAbstractionImpl::publish(const std::string&, const std::string&)
     void publish(const std::string& topic, const std::string& data);
@@ -53,6 +58,10 @@ public:
     /// \copydoc MQTTAbstractionImpl::publish(const std::string&, const std::string&, QOS)
     void publish(const std::string& topic, const std::string& data, QOS qos);
 
+    ///
+    /// \copydoc MQTTAbstractionImpl::publish(const std::string&, const std::string&, QOS, bool)
+    void publish(const std::string& topic, const std::string& data, QOS qos, bool retain);
+
     ///
     /// \copydoc MQTTAbstractionImpl::subscribe(const std::string&)
     void subscribe(const std::string& topic);
@@ -77,6 +86,11 @@ public:
     /// \copydoc MQTTAbstractionImpl::unregister_handler(const std::string&, const Token&)
     void unregister_handler(const std::string& topic, const Token& token);
 
+    ///
+    /// \copydoc MQTTAbstractionImpl::register_connection_handlers(const std::function<void()>&, const std::function<void()>&)
+    void register_connection_handlers(const std::function<void()>& on_disconnected,
+                                      const std::function<void()>& on_reconnected);
+
 private:
     std::unique_ptr<MQTTAbstractionImpl> mqtt_abstraction;
 };
@@ -3,6 +3,7 @@
 #ifndef UTILS_MQTT_ABSTRACTION_IMPL_HPP
 #define UTILS_MQTT_ABSTRACTION_IMPL_HPP
 
+#include <atomic>
 #include <functional>
 #include <future>
 #include <map>
@@ -26,9 +27,10 @@ using json = nlohmann::json;
 
 /// \brief Contains a payload and the topic it was received on with additional QOS
 struct MessageWithQOS : Message {
-    QOS qos; ///< The Quality of Service level
+    QOS qos;     ///< The Quality of Service level
+    bool retain; ///< Whether the broker retains the message for later subscribers
 
-    MessageWithQOS(const std::string& topic, const std::string& payload, QOS qos);
+    MessageWithQOS(const std::string& topic, const std::string& payload, QOS qos, bool retain = false);
 };
 
 ///
@@ -58,6 +60,11 @@ public:
     /// \brief publishes the given \p json on the given \p topic with the given \p qos
     void publish(const std::string& topic, const json& json, QOS qos);
 
+    ///
+    /// \brief publishes the given \p json on the given \p topic with the given \p qos. If \p retain is set, the broker
+    /// keeps it and sends it to everyone subscribing to the topic later, until the next retained message replaces it
+    void publish(const std::string& topic, const json& json, QOS qos, bool retain);
+
     ///
     /// \brief publishes the given \p data on the given \p topic with QOS level 0
     void publish(const std::string& topic, const std::string& data);
@@ -66,6 +73,10 @@ public:
     /// \brief publishes the given \p data on the given \p topic with the given \p qos
     void publish(const std::string& topic, const std::string& data, QOS qos);
 
+    ///
+    /// \brief publishes the given \p data on the given \p topic with the given \p qos, retained if \p retain is set
+    void publish(const std::string& topic, const std::string& data, QOS qos, bool retain);
+
     ///
     /// \brief subscribes to the given \p topic with QOS level 0
     void subscribe(const std::string& topic);
@@ -92,6 +103,14 @@ public:
     /// \brief unsubscribes a handler identified by its \p token from the given \p topic
     void unregister_handler(const std::string& topic, const Token& token);
 
+    ///
+    /// \brief registers \p on_disconnected and \p on_reconnected, which are called from the mqtt main loop when the
+    /// connection to the broker is lost and when it is restored. Without them a lost connection is fatal, with them
+    /// the main loop tries to reconnect until it succeeds or disconnect() is called. After reconnecting, all topics of
+    /// the registered handlers are subscribed to again and the messages published in the meantime are sent.
+    void register_connection_handlers(const std::function<void()>& on_disconnected,
+                                      const std::function<void()>& on_reconnected);
+
     ///
     /// \brief checks if the given \p full_topic matches the given \p wildcard_topic that can contain "+" and "#"
     /// wildcards
@@ -105,6 +124,9 @@ public:
 
 private:
     bool mqtt_is_connected;
+    std::atomic<bool> disconnect_requested{false};
+    std::function<void()> on_disconnected;
+    std::function<void()> on_reconnected;
     std::map<std::string, MessageHandler> message_handlers;
     std::mutex handlers_mutex;
     MessageQueue message_queue;
@@ -123,6 +145,7 @@ private:
 
     static int open_nb_socket(const char* addr, const char* port);
     bool connectBroker(const char* host, const char* port);
+    bool reconnect();
     void on_mqtt_message(std::shared_ptr<Message> message);
     void on_mqtt_connect();
     static void on_mqtt_disconnect();
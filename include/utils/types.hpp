@@ -60,6 +60,8 @@ struct TypedHandler {
 };
 
 using Token = std::shared_ptr<TypedHandler>;
+/// \brief Removes the subscription it was returned for when called
+using UnsubscribeToken = std::function<void()>;
 
 /// \brief MQTT Quality of service
 enum class QOS {
@@ -20,6 +20,7 @@
 
 namespace Everest {
 const auto remote_cmd_res_timeout_seconds = 300;
+const auto retained_var_timeout_milliseconds = 1000;
 const std::array<std::string, 3> TELEMETRY_RESERVED_KEYS = {{"connector_id"}};
 
 Everest::Everest(std::string module_id_, Config config_, bool validate_data_with_schema,
@@ -120,6 +121,21 @@ void Everest::register_on_ready_handler(const std::function<void()>& handler) {
     this->on_ready = std::make_unique<std::function<void()>>(handler);
 }
 
+void Everest::register_module_ready_handler(const std::string& module_id, const std::function<void()>& handler) {
+    BOOST_LOG_FUNCTION();
+
+    Handler module_ready_wrapper = [handler](json data) {
+        // ignore non-truish ready signals, like handle_ready does
+        if (data.is_boolean() && data.get<bool>()) {
+            handler();
+        }
+    };
+    std::shared_ptr<TypedHandler> module_ready = std::make_shared<TypedHandler>(
+        HandlerType::ExternalMQTT, std::make_shared<Handler>(module_ready_wrapper));
+    const auto ready_topic = fmt::format("{}/ready", this->config.mqtt_module_prefix(module_id));
+    this->mqtt_abstraction.register_handler(ready_topic, module_ready, QOS::QOS2);
+}
+
 void Everest::check_code() {
     BOOST_LOG_FUNCTION();
 
@@ -159,6 +175,13 @@ void Everest::disconnect() {
     this->mqtt_abstraction.disconnect();
 }
 
+void Everest::register_connection_handlers(const std::function<void()>& on_disconnected,
+                                           const std::function<void()>& on_reconnected) {
+    BOOST_LOG_FUNCTION();
+
+    this->mqtt_abstraction.register_connection_handlers(on_disconnected, on_reconnected);
+}
+
 json Everest::call_cmd(const Requirement& req, const std::string& cmd_name, json json_args) {
     BOOST_LOG_FUNCTION();
 
@@ -221,10 +244,19 @@ json Everest::call_cmd(const Requirement& req, const std::string& cmd_name, json
 
     std::string call_id = boost::uuids::to_string(boost::uuids::random_generator()());
 
-    std::promise<json> res_promise;
-    std::future<json> res_future = res_promise.get_future();
+    auto res_promise = std::make_shared<std::promise<json>>();
+    std::future<json> res_future = res_promise->get_future();
+    {
+        std::lock_guard<std::mutex> lock(this->pending_calls_mutex);
+        if (this->cmd_calls_cancelled) {
+            throw CommandCancelled(fmt::format(
+                "Call of {}->{}() cancelled",
+                this->config.printable_identifier(connection["module_id"], connection["implementation_id"]), cmd_name));
+        }
+        this->pending_calls[call_id] = res_promise;
+    }
 
-    Handler res_handler = [this, &res_promise, call_id, connection, cmd_name, return_type](json data) {
+    Handler res_handler = [this, call_id, connection, cmd_name, return_type](json data) {
         auto& data_id = data.at("id");
         if (data_id != call_id) {
             EVLOG_debug << fmt::format("RES: data_id != call_id ({} != {})", data_id, call_id);
@@ -235,7 +267,18 @@ json Everest::call_cmd(const Requirement& req, const std::string& cmd_name, json
             "Incoming res {} for {}->{}()", data_id,
             this->config.printable_identifier(connection["module_id"], connection["implementation_id"]), cmd_name);
 
-        res_promise.set_value(std::move(data["retval"]));
+        // the promise is only set once, by the result or by cancel_cmd_calls()
+        std::shared_ptr<std::promise<json>> promise;
+        {
+            std::lock_guard<std::mutex> lock(this->pending_calls_mutex);
+            auto it = this->pending_calls.find(call_id);
+            if (it == this->pending_calls.end()) {
+                return;
+            }
+            promise = std::move(it->second);
+            this->pending_calls.erase(it);
+        }
+        promise->set_value(std::move(data));
     };
 
     const auto cmd_topic =
@@ -259,23 +302,59 @@ json Everest::call_cmd(const Requirement& req, const std::string& cmd_name, json
         res_future_status = res_future.wait_until(res_wait);
     } while (res_future_status == std::future_status::deferred);
 
-    json result;
+    this->mqtt_abstraction.unregister_handler(cmd_topic, res_token);
+
     if (res_future_status == std::future_status::timeout) {
+        {
+            std::lock_guard<std::mutex> lock(this->pending_calls_mutex);
+            this->pending_calls.erase(call_id);
+        }
         EVLOG_AND_THROW(EverestTimeoutError(fmt::format(
             "Timeout while waiting for result of {}->{}()",
             this->config.printable_identifier(connection["module_id"], connection["implementation_id"]), cmd_name)));
-    } else if (res_future_status == std::future_status::ready) {
-        EVLOG_debug << "res future ready";
-        result = res_future.get();
     }
-    this->mqtt_abstraction.unregister_handler(cmd_topic, res_token);
 
-    return result;
+    EVLOG_debug << "res future ready";
+    // throws CommandCancelled if the call was cancelled
+    json data = res_future.get();
+
+    return data["retval"];
+}
+
+void Everest::cancel_cmd_calls() {
+    BOOST_LOG_FUNCTION();
+
+    std::lock_guard<std::mutex> lock(this->pending_calls_mutex);
+    this->cmd_calls_cancelled = true;
+    for (auto& [call_id, promise] : this->pending_calls) {
+        promise->set_exception(std::make_exception_ptr(CommandCancelled(fmt::format("Call {} cancelled", call_id))));
+    }
+    this->pending_calls.clear();
 }
 
 void Everest::publish_var(const std::string& impl_id, const std::string& var_name, json value) {
     BOOST_LOG_FUNCTION();
 
+    this->validate_var(impl_id, var_name, value);
+
+    json var_publish_data = {{"name", var_name}, {"data", value}};
+    this->send_var(impl_id, var_name, var_publish_data.dump());
+}
+
+void Everest::publish_var_formatted(const std::string& impl_id, const std::string& var_name,
+                                    const std::string& value) {
+    BOOST_LOG_FUNCTION();
+
+    this->validate_var(impl_id, var_name, json::parse(value));
+
+    // the same message as publish_var() sends, with the keys in the same order, but value spliced in as it is
+    const auto var_publish_data = fmt::format("{{\"data\":{},\"name\":{}}}", value, json(var_name).dump());
+    this->send_var(impl_id, var_name, var_publish_data);
+}
+
+void Everest::validate_var(const std::string& impl_id, const std::string& var_name, const json& value) {
+    BOOST_LOG_FUNCTION();
+
     // check arguments
     if (this->validate_data_with_schema) {
         auto impl_intf = this->module_classes[impl_id];
@@ -305,16 +384,64 @@ void Everest::publish_var(const std::string& impl_id, const std::string& var_nam
                 this->config.printable_identifier(this->module_id, impl_id), var_name, value.dump(2), e.what())));
         }
     }
+}
 
-    const auto var_topic = fmt::format("{}/var", this->config.mqtt_prefix(this->module_id, impl_id));
+void Everest::send_var(const std::string& impl_id, const std::string& var_name, const std::string& var_publish_data) {
+    BOOST_LOG_FUNCTION();
 
-    json var_publish_data = {{"name", var_name}, {"data", value}};
+    const auto var_topic = fmt::format("{}/var", this->config.mqtt_prefix(this->module_id, impl_id));
 
     // FIXME(kai): implement an efficient way of choosing qos for each variable
     this->mqtt_abstraction.publish(var_topic, var_publish_data, QOS::QOS2);
+
+    // all variables of an implementation share var_topic, so each one is retained on a topic of its own
+    this->mqtt_abstraction.publish(fmt::format("{}/{}", var_topic, var_name), var_publish_data, QOS::QOS2, true);
+}
+
+std::optional<json> Everest::get_latest_var(const Requirement& req, const std::string& var_name) {
+    BOOST_LOG_FUNCTION();
+
+    // resolve requirement
+    json connections = this->config.resolve_requirement(this->module_id, req.id);
+    auto& connection = connections; // this is for a min/max == 1 requirement
+    if (connections.is_array()) {   // this is for every other requirement
+        connection = connections[req.index];
+    }
+
+    const auto retained_topic =
+        fmt::format("{}/var/{}",
+                    this->config.mqtt_prefix(connection["module_id"].get<std::string>(),
+                                             connection["implementation_id"].get<std::string>()),
+                    var_name);
+
+    std::unique_lock<std::mutex> lock(this->latest_vars_mutex);
+    if (this->latest_vars.count(retained_topic) == 0) {
+        this->latest_vars[retained_topic] = std::nullopt;
+        // the subscription is kept, so the value stays up to date and later reads do not wait
+        Handler handler = [this, retained_topic](json const& data) {
+            {
+                std::lock_guard<std::mutex> lock(this->latest_vars_mutex);
+                this->latest_vars[retained_topic] = data;
+            }
+            this->latest_vars_cv.notify_all();
+        };
+        lock.unlock();
+        this->mqtt_abstraction.register_handler(
+            retained_topic,
+            std::make_shared<TypedHandler>(var_name, HandlerType::SubscribeVar, std::make_shared<Handler>(handler)),
+            QOS::QOS2);
+        lock.lock();
+
+        // the broker sends the retained value right after subscribing, there is none if nothing arrives
+        const auto has_value = [this, &retained_topic]() { return this->latest_vars[retained_topic].has_value(); };
+        this->latest_vars_cv.wait_for(lock, std::chrono::milliseconds(retained_var_timeout_milliseconds), has_value);
+    }
+
+    return this->latest_vars[retained_topic];
 }
 
-void Everest::subscribe_var(const Requirement& req, const std::string& var_name, const JsonCallback& callback) {
+UnsubscribeToken Everest::subscribe_var(const Requirement& req, const std::string& var_name,
+                                        const JsonCallback& callback) {
     BOOST_LOG_FUNCTION();
 
     EVLOG_debug << fmt::format("subscribing to var: {}:{}", req.id, var_name);
@@ -368,6 +495,8 @@ void Everest::subscribe_var(const Requirement& req, const std::string& var_name,
     std::shared_ptr<TypedHandler> token =
         std::make_shared<TypedHandler>(var_name, HandlerType::SubscribeVar, std::make_shared<Handler>(handler));
     this->mqtt_abstraction.register_handler(var_topic, token, QOS::QOS2);
+
+    return [this, var_topic, token]() { this->mqtt_abstraction.unregister_handler(var_topic, token); };
 }
 
 void Everest::external_mqtt_publish(const std::string& topic, const std::string& data) {
@@ -544,11 +673,18 @@ void Everest::provide_cmd(const std::string impl_id, const std::string cmd_name,
         json res_data = json({});
         res_data["id"] = data["id"];
 
-        // call real cmd handler
-        res_data["retval"] = handler(data["args"]);
+        // call real cmd handler, an error is sent as the retval, but not validated against the result
+        bool failed = false;
+        try {
+            res_data["retval"] = handler(data["args"]);
+        } catch (const CommandError& e) {
+            EVLOG_debug << fmt::format("Command {} failed: {}", cmd_name, e.what());
+            res_data["retval"] = e.error;
+            failed = true;
+        }
 
         // check retval agains manifest
-        if (this->validate_data_with_schema) {
+        if (this->validate_data_with_schema && !failed) {
             try {
                 // only use validator on non-null return types
                 if (!(res_data["retval"].is_null() &&
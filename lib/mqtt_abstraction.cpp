@@ -35,6 +35,11 @@ void MQTTAbstraction::publish(const std::string& topic, const json& json, QOS qo
     mqtt_abstraction->publish(topic, json, qos);
 }
 
+void MQTTAbstraction::publish(const std::string& topic, const json& json, QOS qos, bool retain) {
+    BOOST_LOG_FUNCTION();
+    mqtt_abstraction->publish(topic, json, qos, retain);
+}
+
 void MQTTAbstraction::publish(const std::string& topic, const std::string& data) {
     BOOST_LOG_FUNCTION();
     mqtt_abstraction->publish(topic, data);
@@ -45,6 +50,11 @@ void MQTTAbstraction::publish(const std::string& topic, const std::string& data,
     mqtt_abstraction->publish(topic, data, qos);
 }
 
+void MQTTAbstraction::publish(const std::string& topic, const std::string& data, QOS qos, bool retain) {
+    BOOST_LOG_FUNCTION();
+    mqtt_abstraction->publish(topic, data, qos, retain);
+}
+
 void MQTTAbstraction::subscribe(const std::string& topic) {
     BOOST_LOG_FUNCTION();
     mqtt_abstraction->subscribe(topic);
@@ -75,4 +85,10 @@ void MQTTAbstraction::unregister_handler(const std::string& topic, const Token&
     mqtt_abstraction->unregister_handler(topic, token);
 }
 
+void MQTTAbstraction::register_connection_handlers(const std::function<void()>& on_disconnected,
+                                                   const std::function<void()>& on_reconnected) {
+    BOOST_LOG_FUNCTION();
+    mqtt_abstraction->register_connection_handlers(on_disconnected, on_reconnected);
+}
+
 } // namespace Everest
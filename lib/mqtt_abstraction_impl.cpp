@@ -22,9 +22,10 @@
 namespace Everest {
 const auto mqtt_sync_sleep_milliseconds = 10;
 const auto mqtt_keep_alive = 400;
+const auto mqtt_reconnect_interval_milliseconds = 1000;
 
-MessageWithQOS::MessageWithQOS(const std::string& topic, const std::string& payload, QOS qos) :
-    Message(topic, payload), qos(qos) {
+MessageWithQOS::MessageWithQOS(const std::string& topic, const std::string& payload, QOS qos, bool retain) :
+    Message(topic, payload), qos(qos), retain(retain) {
 }
 
 MQTTAbstractionImpl::MQTTAbstractionImpl(const std::string& mqtt_server_address, const std::string& mqtt_server_port,
@@ -66,6 +67,7 @@ bool MQTTAbstractionImpl::connect() {
 void MQTTAbstractionImpl::disconnect() {
     BOOST_LOG_FUNCTION();
 
+    this->disconnect_requested = true;
     mqtt_disconnect(&this->mqtt_client);
     // FIXME(kai): always set connected to false for the moment
     this->mqtt_is_connected = false;
@@ -80,8 +82,14 @@ void MQTTAbstractionImpl::publish(const std::string& topic, const json& json) {
 void MQTTAbstractionImpl::publish(const std::string& topic, const json& json, QOS qos) {
     BOOST_LOG_FUNCTION();
 
+    publish(topic, json, qos, false);
+}
+
+void MQTTAbstractionImpl::publish(const std::string& topic, const json& json, QOS qos, bool retain) {
+    BOOST_LOG_FUNCTION();
+
     std::string data = json.dump();
-    publish(topic, data, qos);
+    publish(topic, data, qos, retain);
 }
 
 void MQTTAbstractionImpl::publish(const std::string& topic, const std::string& data) {
@@ -93,6 +101,12 @@ void MQTTAbstractionImpl::publish(const std::string& topic, const std::string& d
 void MQTTAbstractionImpl::publish(const std::string& topic, const std::string& data, QOS qos) {
     BOOST_LOG_FUNCTION();
 
+    publish(topic, data, qos, false);
+}
+
+void MQTTAbstractionImpl::publish(const std::string& topic, const std::string& data, QOS qos, bool retain) {
+    BOOST_LOG_FUNCTION();
+
     auto publish_flags = 0;
     switch (qos) {
     case QOS::QOS0:
@@ -108,10 +122,13 @@ void MQTTAbstractionImpl::publish(const std::string& topic, const std::string& d
     default:
         break;
     }
+    if (retain) {
+        publish_flags |= MQTT_PUBLISH_RETAIN;
+    }
 
     if (!this->mqtt_is_connected) {
         const std::lock_guard<std::mutex> lock(messages_before_connected_mutex);
-        this->messages_before_connected.push_back(std::make_shared<MessageWithQOS>(topic, data, qos));
+        this->messages_before_connected.push_back(std::make_shared<MessageWithQOS>(topic, data, qos, retain));
         return;
     }
 
@@ -166,9 +183,22 @@ std::future<void> MQTTAbstractionImpl::spawn_main_loop_thread() {
                 if (error != MQTT_OK) {
                     EVLOG_error << fmt::format("Error during MQTT sync: {}", mqtt_error_str(error));
 
-                    on_mqtt_disconnect();
-
-                    return;
+                    if (this->on_reconnected == nullptr) {
+                        on_mqtt_disconnect();
+                        return;
+                    }
+
+                    {
+                        // messages published from now on are sent after reconnecting
+                        const std::lock_guard<std::mutex> lock(messages_before_connected_mutex);
+                        this->mqtt_is_connected = false;
+                    }
+                    this->on_disconnected();
+                    if (!reconnect()) {
+                        return;
+                    }
+                    this->on_reconnected();
+                    continue;
                 }
                 std::this_thread::sleep_for(std::chrono::milliseconds(mqtt_sync_sleep_milliseconds));
             }
@@ -266,7 +296,7 @@ void MQTTAbstractionImpl::on_mqtt_connect() {
         const std::lock_guard<std::mutex> lock(messages_before_connected_mutex);
         this->mqtt_is_connected = true;
         for (auto& message : this->messages_before_connected) {
-            this->publish(message->topic, message->payload, message->qos);
+            this->publish(message->topic, message->payload, message->qos, message->retain);
         }
         this->messages_before_connected.clear();
     }
@@ -319,6 +349,14 @@ void MQTTAbstractionImpl::register_handler(const std::string& topic, std::shared
     EVLOG_debug << fmt::format("#handler[{}] = {}", topic, this->message_handlers[topic].count_handlers());
 }
 
+void MQTTAbstractionImpl::register_connection_handlers(const std::function<void()>& on_disconnected,
+                                                       const std::function<void()>& on_reconnected) {
+    BOOST_LOG_FUNCTION();
+
+    this->on_disconnected = on_disconnected;
+    this->on_reconnected = on_reconnected;
+}
+
 void MQTTAbstractionImpl::unregister_handler(const std::string& topic, const Token& token) {
     BOOST_LOG_FUNCTION();
 
@@ -372,6 +410,21 @@ bool MQTTAbstractionImpl::connectBroker(const char* host, const char* port) {
     return false;
 }
 
+bool MQTTAbstractionImpl::reconnect() {
+    BOOST_LOG_FUNCTION();
+
+    close(this->mqtt_client.socketfd);
+    while (!this->disconnect_requested) {
+        EVLOG_info << fmt::format("Reconnecting to MQTT broker: {}:{}", this->mqtt_server_address,
+                                  this->mqtt_server_port);
+        if (connectBroker(this->mqtt_server_address.c_str(), this->mqtt_server_port.c_str())) {
+            return true;
+        }
+        std::this_thread::sleep_for(std::chrono::milliseconds(mqtt_reconnect_interval_milliseconds));
+    }
+    return false;
+}
+
 int MQTTAbstractionImpl::open_nb_socket(const char* addr, const char* port) {
     BOOST_LOG_FUNCTION();
 